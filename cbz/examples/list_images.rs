@@ -12,9 +12,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut archive = CbzArchive::from_file(path).await?;
 
     println!("Archive contains {} entries", archive.len());
-    println!("\nImages found:");
+    println!("\nPages in reading order:");
 
-    for name in archive.image_names() {
+    for name in archive.pages() {
         println!("  - {}", name);
     }
 
@@ -0,0 +1,73 @@
+use cbz::mokuro::{read_mokuro_pages, write_mokuro_pages};
+use cbz::{CbzArchive, MokuroVolume, embed_ocr_sidecar};
+use std::path::Path;
+
+const USAGE: &str = "Usage:
+  {0} import <comic.cbz> <volume.mokuro | _ocr/volume>
+      Embeds Mokuro's results in the archive
+  {0} export <comic.cbz> <volume.mokuro | folder>
+      Writes the archive's OCR results as a .mokuro file or per-page files";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 4 {
+        eprintln!("{}", USAGE.replace("{0}", &args[0]));
+        std::process::exit(1);
+    }
+
+    let (command, archive_path, mokuro_path) = (&args[1], &args[2], Path::new(&args[3]));
+    let mut archive = CbzArchive::open(archive_path)?;
+    let pages = archive.pages();
+
+    match command.as_str() {
+        "import" => {
+            let sidecar = if mokuro_path.is_dir() {
+                read_mokuro_pages(mokuro_path, &pages)?
+            } else {
+                MokuroVolume::parse(&std::fs::read(mokuro_path)?)?.to_sidecar(&pages)
+            };
+            // The archive is replaced, close it first.
+            drop(archive);
+            embed_ocr_sidecar(archive_path, &sidecar)?;
+            println!(
+                "Imported {} of {} pages into {}",
+                sidecar.pages.len(),
+                pages.len(),
+                archive_path
+            );
+        }
+        "export" => {
+            let Some(sidecar) = archive.ocr_sidecar()? else {
+                return Err(format!("{} has no OCR results", archive_path).into());
+            };
+            let is_mokuro_file = mokuro_path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("mokuro"));
+            if is_mokuro_file {
+                let volume = file_stem(Path::new(archive_path));
+                let title = archive
+                    .metadata()?
+                    .and_then(|info| info.series.or(info.title))
+                    .unwrap_or_else(|| volume.clone());
+                let mokuro = MokuroVolume::from_sidecar(&sidecar, &pages, &title, &volume);
+                std::fs::write(mokuro_path, mokuro.to_json())?;
+            } else {
+                write_mokuro_pages(&sidecar, mokuro_path)?;
+            }
+            println!(
+                "Exported {} pages to {}",
+                sidecar.pages.len(),
+                mokuro_path.display()
+            );
+        }
+        _ => return Err(format!("Unknown command {}", command).into()),
+    }
+
+    Ok(())
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
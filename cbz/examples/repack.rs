@@ -0,0 +1,102 @@
+use cbz::{Manga, RepackOptions, open_source, repack};
+use std::fs::File;
+use std::io::BufWriter;
+
+const OPTIONS: &str = "Options:
+  --webp <quality>      Transcode pages to WebP (needs the transcode feature)
+  --avif <quality>      Transcode pages to AVIF (needs the transcode feature)
+  --title <title>       Set the title
+  --series <series>     Set the series
+  --number <number>     Set the issue number
+  --volume <volume>     Set the volume
+  --writer <writer>     Set the writer
+  --language <code>     Set the language, e.g. ja
+  --manga <yes|no|rtl>  Set whether the comic is manga, rtl for right to left";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        eprintln!(
+            "Usage: {} <input> <output.cbz> [options]\n\n{}",
+            args[0], OPTIONS
+        );
+        std::process::exit(1);
+    }
+
+    let (input, output) = (&args[1], &args[2]);
+    let mut source = open_source(input)?;
+
+    let mut options = RepackOptions::default();
+    // Edits apply on top of the source's metadata.
+    let mut info = source.metadata()?.unwrap_or_default();
+    let mut edited = false;
+    let mut rest = args[3..].iter();
+    while let Some(flag) = rest.next() {
+        let Some(value) = rest.next() else {
+            return Err(format!("Missing value for {}", flag).into());
+        };
+        edited |= !matches!(flag.as_str(), "--webp" | "--avif");
+        match flag.as_str() {
+            "--webp" | "--avif" => set_transcode(&mut options, flag, value.parse()?)?,
+            "--title" => info.title = Some(value.clone()),
+            "--series" => info.series = Some(value.clone()),
+            "--number" => info.number = Some(value.clone()),
+            "--volume" => info.volume = Some(value.parse()?),
+            "--writer" => info.writer = Some(value.clone()),
+            "--language" => info.language = Some(value.clone()),
+            "--manga" => info.manga = parse_manga(value)?,
+            _ => return Err(format!("Unknown option {}", flag).into()),
+        }
+    }
+    if edited {
+        options.comic_info = Some(info);
+    }
+
+    let writer = BufWriter::new(File::create(output)?);
+    let report = repack(source.as_mut(), writer, &options)?;
+
+    println!("Wrote {} pages to {}", report.pages, output);
+    if report.transcoded > 0 {
+        println!("Transcoded {} pages", report.transcoded);
+    }
+    println!(
+        "Pages: {} bytes -> {} bytes",
+        report.bytes_in, report.bytes_out
+    );
+
+    Ok(())
+}
+
+fn parse_manga(value: &str) -> Result<Manga, Box<dyn std::error::Error>> {
+    Ok(match value {
+        "yes" => Manga::Yes,
+        "no" => Manga::No,
+        "rtl" => Manga::YesAndRightToLeft,
+        _ => return Err(format!("Unknown --manga value {}", value).into()),
+    })
+}
+
+#[cfg(feature = "transcode")]
+fn set_transcode(
+    options: &mut RepackOptions,
+    flag: &str,
+    quality: u8,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use cbz::{Transcode, TranscodeFormat};
+
+    let format = match flag {
+        "--webp" => TranscodeFormat::WebP,
+        _ => TranscodeFormat::Avif,
+    };
+    options.transcode = Some(Transcode { format, quality });
+    Ok(())
+}
+
+#[cfg(not(feature = "transcode"))]
+fn set_transcode(
+    _options: &mut RepackOptions,
+    flag: &str,
+    _quality: u8,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err(format!("{} needs the transcode feature", flag).into())
+}
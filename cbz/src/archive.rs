@@ -1,17 +1,50 @@
-use crate::error::{CbzError, Result};
-use std::io::{self, Cursor, Read};
+use crate::chapter::{
+    Chapter, NESTED_SEPARATOR, archive_title, group_by_directory, is_archive_name, split_nested,
+};
+use crate::comic_info::ComicInfo;
+use crate::error::{CbzError, Result, entry_error};
+use crate::filter::{FilterPolicy, SkipReason, SkippedEntry};
+use crate::names::{NameEncoding, decode_names};
+use crate::ocr::{OCR_SIDECAR_NAME, OcrSidecar};
+use crate::source::{
+    ComicSource, find_comic_info, image_entry, parse_comic_info, reading_order, skip_reason,
+};
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, SeekFrom};
 use std::path::Path;
-use zip::ZipArchive;
+use zip::{CompressionMethod, ZipArchive};
+
+/// Bytes of compressed nested archives kept decompressed for the next pages,
+/// least recently used dropped first. The one being read is always kept.
+const NESTED_CACHE_BYTES: u64 = 128 * 1024 * 1024;
 
 pub struct CbzArchive<R: Read + io::Seek> {
     archive: ZipArchive<R>,
+    raw_names: Vec<Vec<u8>>,
+    // Decoded entry names, by zip index.
+    names: Vec<String>,
+    indices: HashMap<String, usize>,
+    encoding: NameEncoding,
+    // Format sniffed from each entry's first bytes, `None` when unreadable.
+    formats: Vec<Option<ImageFormat>>,
+    sizes: Vec<u64>,
+    encrypted: Vec<bool>,
+    password: Option<Vec<u8>>,
+    filter: FilterPolicy,
+    // Length of the whole archive.
+    size: u64,
+    // Compressed nested archives decompressed so far, least recently used
+    // first. Stored ones are read in place instead.
+    inner: Vec<(String, CbzArchive<Cursor<Vec<u8>>>)>,
+    // Pages of each nested archive, once listed.
+    nested_pages: HashMap<String, Vec<String>>,
 }
 
 impl CbzArchive<Cursor<Vec<u8>>> {
     pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
-        let cursor = Cursor::new(data);
-        let archive = ZipArchive::new(cursor)?;
-        Ok(Self { archive })
+        Self::from_reader(Cursor::new(data))
     }
 
     pub async fn from_file(path: impl AsRef<Path>) -> Result<Self> {
@@ -20,10 +53,127 @@ impl CbzArchive<Cursor<Vec<u8>>> {
     }
 }
 
+impl CbzArchive<BufReader<File>> {
+    /// Opens the archive in place, reading entries from disk on demand.
+    ///
+    /// Only the central directory is loaded, so memory use does not grow with
+    /// the size of the archive.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path.as_ref())?;
+        Self::from_reader(BufReader::new(file))
+    }
+}
+
+impl CbzArchive<Cursor<Mmap>> {
+    /// Opens the archive by memory-mapping the file.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the archive is alive,
+    /// see [`memmap2::Mmap::map`].
+    pub unsafe fn open_mmap(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path.as_ref())?;
+        let map = unsafe { Mmap::map(&file)? };
+        Self::from_reader(Cursor::new(map))
+    }
+}
+
 impl<R: Read + io::Seek> CbzArchive<R> {
     pub fn from_reader(reader: R) -> Result<Self> {
-        let archive = ZipArchive::new(reader)?;
-        Ok(Self { archive })
+        Self::from_reader_sniffing(reader, true)
+    }
+
+    // Without `sniff`, pages are only told apart by their extension, which
+    // is enough to read an entry by name.
+    fn from_reader_sniffing(mut reader: R, sniff: bool) -> Result<Self> {
+        let size = reader.seek(SeekFrom::End(0))?;
+        let mut archive = ZipArchive::new(reader)?;
+        let mut raw_names = Vec::with_capacity(archive.len());
+        let mut sizes = Vec::with_capacity(archive.len());
+        let mut encrypted = Vec::with_capacity(archive.len());
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i)?;
+            raw_names.push(entry.name_raw().to_vec());
+            sizes.push(entry.size());
+            encrypted.push(entry.encrypted());
+        }
+        let formats = (0..archive.len())
+            .map(|i| sniff.then(|| sniff_entry(&mut archive, i, None)).flatten())
+            .collect();
+        let mut cbz = Self {
+            archive,
+            raw_names,
+            names: Vec::new(),
+            indices: HashMap::new(),
+            encoding: NameEncoding::Auto,
+            formats,
+            sizes,
+            encrypted,
+            password: None,
+            filter: FilterPolicy::default(),
+            size,
+            inner: Vec::new(),
+            nested_pages: HashMap::new(),
+        };
+        cbz.set_name_encoding(NameEncoding::Auto);
+        Ok(cbz)
+    }
+
+    /// Encoding used to decode entry names, as detected or set.
+    pub fn name_encoding(&self) -> NameEncoding {
+        self.encoding
+    }
+
+    /// Decodes entry names again with `encoding`, for archives the detection
+    /// gets wrong.
+    pub fn set_name_encoding(&mut self, encoding: NameEncoding) {
+        let cp437: Vec<String> = (0..self.archive.len())
+            .map(|i| {
+                self.archive
+                    .name_for_index(i)
+                    .unwrap_or_default()
+                    .to_string()
+            })
+            .collect();
+        let (encoding, names) = decode_names(&self.raw_names, &cp437, encoding);
+
+        self.indices.clear();
+        for (index, name) in names.iter().enumerate() {
+            self.indices.entry(name.clone()).or_insert(index);
+        }
+        self.names = names;
+        self.encoding = encoding;
+        self.clear_nested();
+    }
+
+    /// Whether any entry is encrypted, with ZipCrypto or AES.
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted.contains(&true)
+    }
+
+    /// Sets the password used to decrypt entries.
+    ///
+    /// The password is checked against the first encrypted entry and kept
+    /// only if it decrypts it, so a wrong one fails here with
+    /// [`CbzError::WrongPassword`] rather than on every page.
+    pub fn set_password(&mut self, password: &str) -> Result<()> {
+        let previous = self.password.replace(password.as_bytes().to_vec());
+        if let Some(index) = self.encrypted.iter().position(|&encrypted| encrypted) {
+            let name = self.names[index].clone();
+            if let Err(error) = self.read_entry(&name) {
+                self.password = previous;
+                return Err(error);
+            }
+        }
+        // Encrypted entries could not be sniffed without the password.
+        for index in 0..self.archive.len() {
+            if self.encrypted[index] {
+                self.formats[index] =
+                    sniff_entry(&mut self.archive, index, self.password.as_deref());
+            }
+        }
+        self.clear_nested();
+        Ok(())
     }
 
     pub fn len(&self) -> usize {
@@ -35,31 +185,265 @@ impl<R: Read + io::Seek> CbzArchive<R> {
     }
 
     pub fn file_names(&self) -> Vec<String> {
-        (0..self.archive.len())
-            .filter_map(|i| self.archive.name_for_index(i).map(|s| s.to_string()))
-            .collect()
+        self.names.clone()
+    }
+
+    pub fn filter(&self) -> &FilterPolicy {
+        &self.filter
     }
 
+    /// Replaces the policy deciding which entries are junk rather than pages.
+    pub fn set_filter(&mut self, filter: FilterPolicy) {
+        self.filter = filter;
+        self.clear_nested();
+    }
+
+    /// Returns the entries holding images, in archive order.
+    ///
+    /// Entries are recognized by their content, so extensionless or misnamed
+    /// pages are found too. The extension is only trusted for entries whose
+    /// header could not be read, such as encrypted ones. Entries the
+    /// [`FilterPolicy`] rejects are left out, see [`Self::skipped_entries`].
     pub fn image_names(&self) -> Vec<String> {
-        self.file_names()
-            .into_iter()
-            .filter(|name| {
-                let lower = name.to_lowercase();
-                lower.ends_with(".jpg")
-                    || lower.ends_with(".jpeg")
-                    || lower.ends_with(".png")
-                    || lower.ends_with(".gif")
-                    || lower.ends_with(".webp")
-                    || lower.ends_with(".bmp")
+        (0..self.names.len())
+            .filter(|&index| !self.is_dir(index) && self.skip_reason(index).is_none())
+            .map(|index| self.names[index].clone())
+            .collect()
+    }
+
+    /// Lists the files left out of [`Self::image_names`], and why.
+    pub fn skipped_entries(&self) -> Vec<SkippedEntry> {
+        (0..self.names.len())
+            .filter(|&index| !self.is_dir(index))
+            .filter_map(|index| {
+                Some(SkippedEntry {
+                    name: self.names[index].clone(),
+                    reason: self.skip_reason(index)?,
+                })
             })
             .collect()
     }
 
+    fn is_dir(&self, index: usize) -> bool {
+        self.names[index].ends_with('/')
+    }
+
+    fn skip_reason(&self, index: usize) -> Option<SkipReason> {
+        let name = &self.names[index];
+        match skip_reason(&self.filter, name, self.sizes[index], self.formats[index]) {
+            Some(SkipReason::NotAnImage) if is_archive_name(name) => {
+                Some(SkipReason::NestedArchive)
+            }
+            reason => reason,
+        }
+    }
+
+    /// Names of the zip archives stored inside this one.
+    pub fn nested_archives(&self) -> Vec<String> {
+        (0..self.names.len())
+            .filter(|&index| self.skip_reason(index) == Some(SkipReason::NestedArchive))
+            .map(|index| self.names[index].clone())
+            .collect()
+    }
+
+    /// Bytes of nested archives held decompressed in memory, which
+    /// [`Self::clear_cache`] frees.
+    pub fn cached_size(&self) -> u64 {
+        self.inner.iter().map(|(_, archive)| archive.size).sum()
+    }
+
+    /// Drops the nested archives held in memory, to be read again when a
+    /// page of theirs is.
+    pub fn clear_cache(&mut self) {
+        self.inner.clear();
+    }
+
+    fn clear_nested(&mut self) {
+        self.inner.clear();
+        self.nested_pages.clear();
+    }
+
+    /// Runs `f` on the nested archive `name`.
+    ///
+    /// Stored archives, the usual way to nest them, are read in place through
+    /// this one: only their central directory and the entries `f` reads are.
+    /// Compressed or encrypted ones have to be decompressed whole, and are
+    /// kept for the next pages up to [`NESTED_CACHE_BYTES`].
+    fn with_nested<T>(
+        &mut self,
+        name: &str,
+        sniff: bool,
+        f: impl FnOnce(&mut dyn Nested) -> Result<T>,
+    ) -> Result<T> {
+        let index = *self
+            .indices
+            .get(name)
+            .ok_or_else(|| CbzError::NotFound(name.to_string()))?;
+        let (method, start) = {
+            let entry = self.archive.by_index_raw(index)?;
+            (entry.compression(), entry.data_start())
+        };
+        if method == CompressionMethod::Stored && !self.encrypted[index] {
+            let filter = self.filter.clone();
+            let entry = EntrySeek {
+                inner: self.archive.by_index_seek(index)?,
+                start,
+            };
+            // Boxed so that archives nested deeper still read through one type.
+            let entry: Box<dyn ReadSeek + '_> = Box::new(entry);
+            let mut inner = CbzArchive::from_reader_sniffing(entry, sniff)?;
+            inner.filter = filter;
+            return f(&mut inner);
+        }
+
+        match self.inner.iter().position(|(cached, _)| cached == name) {
+            Some(position) => {
+                let used = self.inner.remove(position);
+                self.inner.push(used);
+            }
+            None => {
+                let mut archive = CbzArchive::from_bytes(self.read_entry(name)?)?;
+                archive.filter = self.filter.clone();
+                self.inner.push((name.to_string(), archive));
+                while self.inner.len() > 1 && self.cached_size() > NESTED_CACHE_BYTES {
+                    self.inner.remove(0);
+                }
+            }
+        }
+        let (_, inner) = self.inner.last_mut().unwrap();
+        f(inner)
+    }
+
+    /// Returns the pages in reading order, chapter after chapter.
+    ///
+    /// See [`Self::chapters`] for how pages are grouped and ordered.
+    pub fn pages(&mut self) -> Vec<String> {
+        self.chapters()
+            .into_iter()
+            .flat_map(|chapter| chapter.pages)
+            .collect()
+    }
+
+    /// Groups pages into chapters, one per subdirectory and one per nested
+    /// archive, in natural order.
+    ///
+    /// Within a chapter, pages follow the `<Pages>` list of `ComicInfo.xml`
+    /// when there is one, and natural order (see [`crate::natural_cmp`])
+    /// otherwise. Pages of a nested archive are named
+    /// `<archive>!/<page>` (see [`NESTED_SEPARATOR`]) and can be read like
+    /// any other entry. Archives nested deeper than one level are ignored.
+    pub fn chapters(&mut self) -> Vec<Chapter> {
+        let mut chapters = group_by_directory(self.own_pages());
+        for name in self.nested_archives() {
+            let pages = match self.nested_pages.get(&name) {
+                Some(pages) => pages.clone(),
+                None => {
+                    let Ok(pages) = self.with_nested(&name, true, |inner| Ok(inner.own_pages()))
+                    else {
+                        continue;
+                    };
+                    let pages: Vec<String> = pages
+                        .into_iter()
+                        .map(|page| format!("{}{}{}", name, NESTED_SEPARATOR, page))
+                        .collect();
+                    self.nested_pages.insert(name.clone(), pages.clone());
+                    pages
+                }
+            };
+            if !pages.is_empty() {
+                let title = archive_title(&name);
+                chapters.push((name, Chapter { title, pages }));
+            }
+        }
+        chapters.sort_by(|(a, _), (b, _)| crate::natural_cmp(a, b));
+        chapters.into_iter().map(|(_, chapter)| chapter).collect()
+    }
+
+    /// Images of this archive itself, leaving nested archives out.
+    fn own_pages(&mut self) -> Vec<String> {
+        let info = self.metadata().ok().flatten();
+        reading_order(self.image_names(), info.as_ref())
+    }
+
+    /// Parses `ComicInfo.xml`, if the archive has one.
+    pub fn metadata(&mut self) -> Result<Option<ComicInfo>> {
+        let Some(name) = find_comic_info(self.names.iter()).cloned() else {
+            return Ok(None);
+        };
+        let data = self.read_entry(&name)?;
+        parse_comic_info(&data).map(Some)
+    }
+
+    /// Parses the OCR results embedded as [`OCR_SIDECAR_NAME`], if any.
+    pub fn ocr_sidecar(&mut self) -> Result<Option<OcrSidecar>> {
+        if !self.indices.contains_key(OCR_SIDECAR_NAME) {
+            return Ok(None);
+        }
+        let data = self.read_entry(OCR_SIDECAR_NAME)?;
+        OcrSidecar::parse(&data).map(Some)
+    }
+
+    /// Reads an entry, or a page of a nested archive given as
+    /// `<archive>!/<page>`.
     pub fn read_entry(&mut self, name: &str) -> Result<Vec<u8>> {
-        let mut file = self.archive.by_name(name)?;
-        let mut buffer = Vec::with_capacity(file.size() as usize);
-        io::copy(&mut file, &mut buffer)?;
-        Ok(buffer)
+        let Some(&index) = self.indices.get(name) else {
+            return match split_nested(name) {
+                Some((archive, page)) if self.indices.contains_key(archive) => {
+                    self.with_nested(archive, false, |inner| inner.read_entry(page, u64::MAX))
+                }
+                _ => Err(CbzError::NotFound(name.to_string())),
+            };
+        };
+        let method = self.archive.by_index_raw(index)?.compression();
+        #[cfg(feature = "lzma")]
+        if method == zip::CompressionMethod::Lzma && !self.encrypted[index] {
+            return crate::lzma::read_lzma(&mut self.archive, index, name);
+        }
+        if !self.encrypted[index] {
+            return read_index(&mut self.archive, index, None, u64::MAX)
+                .map_err(|e| entry_error(name, method, e));
+        }
+        let Some(password) = &self.password else {
+            return Err(CbzError::PasswordRequired(name.to_string()));
+        };
+        match read_index(&mut self.archive, index, Some(password), u64::MAX) {
+            Ok(data) => Ok(data),
+            Err(e) => match entry_error(name, method, e) {
+                // ZipCrypto checks a single byte of the key, so one wrong
+                // password in 256 gets past it and fails the CRC instead.
+                CbzError::CrcMismatch(name) => Err(CbzError::WrongPassword(name)),
+                other => Err(other),
+            },
+        }
+    }
+
+    /// Reads the first `len` bytes at most of an entry, like
+    /// [`Self::read_entry`] but without decompressing the rest, which also
+    /// leaves its CRC unchecked.
+    pub fn read_entry_header(&mut self, name: &str, len: u64) -> Result<Vec<u8>> {
+        let Some(&index) = self.indices.get(name) else {
+            return match split_nested(name) {
+                Some((archive, page)) if self.indices.contains_key(archive) => {
+                    self.with_nested(archive, false, |inner| inner.read_entry(page, len))
+                }
+                _ => Err(CbzError::NotFound(name.to_string())),
+            };
+        };
+        let method = self.archive.by_index_raw(index)?.compression();
+        // LZMA entries go through their own reader, which reads them whole.
+        #[cfg(feature = "lzma")]
+        if method == zip::CompressionMethod::Lzma && !self.encrypted[index] {
+            let mut data = self.read_entry(name)?;
+            data.truncate(len as usize);
+            return Ok(data);
+        }
+        let password = match (self.encrypted[index], &self.password) {
+            (false, _) => None,
+            (true, Some(password)) => Some(password.as_slice()),
+            (true, None) => return Err(CbzError::PasswordRequired(name.to_string())),
+        };
+        read_index(&mut self.archive, index, password, len)
+            .map_err(|e| entry_error(name, method, e))
     }
 
     pub fn read_image(&mut self, name: &str) -> Result<ImageEntry> {
@@ -72,14 +456,159 @@ impl<R: Read + io::Seek> CbzArchive<R> {
         })
     }
 
+    /// Reads every image, yielding an error for each one that fails to read.
     pub fn iter_images(&mut self) -> impl Iterator<Item = Result<ImageEntry>> + '_ {
         let names = self.image_names();
-        names.into_iter().filter_map(move |name| {
-            let data = self.read_entry(&name).ok()?;
-            let format = detect_image_format(&data).ok()?;
-            Some(Ok(ImageEntry { name, data, format }))
-        })
+        names.into_iter().map(move |name| self.read_image(&name))
     }
+
+    /// Reads every file entry in full, checking its CRC, and reports the
+    /// ones that fail.
+    pub fn verify(&mut self) -> HealthReport {
+        let names: Vec<String> = (0..self.names.len())
+            .filter(|&index| !self.is_dir(index))
+            .map(|index| self.names[index].clone())
+            .collect();
+        let mut report = HealthReport {
+            checked: names.len(),
+            errors: Vec::new(),
+        };
+        for name in names {
+            if let Err(error) = self.read_entry(&name) {
+                report.errors.push(EntryError { name, error });
+            }
+        }
+        report
+    }
+}
+
+trait ReadSeek: Read + io::Seek {}
+
+impl<T: Read + io::Seek> ReadSeek for T {}
+
+/// A stored entry read in place. zip reports its positions from the start
+/// of the outer archive rather than of the entry, which this corrects.
+struct EntrySeek<R> {
+    inner: R,
+    start: u64,
+}
+
+impl<R: Read> Read for EntrySeek<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: io::Seek> io::Seek for EntrySeek<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        Ok(self.inner.seek(pos)? - self.start)
+    }
+}
+
+/// What is read from a nested archive, whichever way it was opened.
+trait Nested {
+    fn own_pages(&mut self) -> Vec<String>;
+
+    fn read_entry(&mut self, name: &str, len: u64) -> Result<Vec<u8>>;
+}
+
+impl<R: Read + io::Seek> Nested for CbzArchive<R> {
+    fn own_pages(&mut self) -> Vec<String> {
+        CbzArchive::own_pages(self)
+    }
+
+    fn read_entry(&mut self, name: &str, len: u64) -> Result<Vec<u8>> {
+        self.read_entry_header(name, len)
+    }
+}
+
+impl<R: Read + io::Seek> ComicSource for CbzArchive<R> {
+    fn pages(&mut self) -> Vec<String> {
+        CbzArchive::pages(self)
+    }
+
+    fn read_page(&mut self, name: &str) -> Result<ImageEntry> {
+        self.read_image(name)
+    }
+
+    fn read_page_header(&mut self, name: &str, len: u64) -> Result<ImageEntry> {
+        let data = self.read_entry_header(name, len)?;
+        image_entry(name, data)
+    }
+
+    fn metadata(&mut self) -> Result<Option<ComicInfo>> {
+        CbzArchive::metadata(self)
+    }
+
+    fn ocr_sidecar(&mut self) -> Result<Option<OcrSidecar>> {
+        CbzArchive::ocr_sidecar(self)
+    }
+
+    fn chapters(&mut self) -> Vec<Chapter> {
+        CbzArchive::chapters(self)
+    }
+
+    fn cached_size(&self) -> u64 {
+        CbzArchive::cached_size(self)
+    }
+
+    fn clear_cache(&mut self) {
+        CbzArchive::clear_cache(self)
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+// Reads up to `limit` bytes of the entry; the CRC is only checked when that
+// reaches its end.
+fn read_index<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    index: usize,
+    password: Option<&[u8]>,
+    limit: u64,
+) -> Result<Vec<u8>> {
+    let mut file = match password {
+        Some(password) => archive.by_index_decrypt(index, password)?,
+        None => archive.by_index(index)?,
+    };
+    let (size, crc) = (file.size(), file.crc32());
+    let mut buffer = Vec::with_capacity(size.min(limit) as usize);
+    match io::copy(&mut (&mut file).take(limit), &mut buffer) {
+        Ok(_) => Ok(buffer),
+        // zip checks the CRC once the whole entry is read and fails with the
+        // same kind of error as for corrupt compressed data, so check it
+        // again to tell them apart.
+        Err(e)
+            if e.kind() == io::ErrorKind::InvalidData
+                && buffer.len() as u64 == size
+                && crc32fast::hash(&buffer) != crc =>
+        {
+            Err(CbzError::CrcMismatch(file.name().to_string()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Result of [`CbzArchive::verify`].
+#[derive(Debug)]
+pub struct HealthReport {
+    /// Number of file entries read.
+    pub checked: usize,
+    pub errors: Vec<EntryError>,
+}
+
+impl HealthReport {
+    pub fn is_healthy(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct EntryError {
+    pub name: String,
+    pub error: CbzError,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -89,48 +618,141 @@ pub enum ImageFormat {
     Gif,
     WebP,
     Bmp,
+    Avif,
+    Jxl,
+    Tiff,
     Unknown,
 }
 
 impl std::fmt::Display for ImageFormat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.mime_type())
+    }
+}
+
+impl ImageFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::WebP => "image/webp",
+            ImageFormat::Bmp => "image/bmp",
+            ImageFormat::Avif => "image/avif",
+            ImageFormat::Jxl => "image/jxl",
+            ImageFormat::Tiff => "image/tiff",
+            ImageFormat::Unknown => "application/octet-stream",
+        }
+    }
+
+    /// File extension, without the dot, `bin` for unknown formats.
+    pub fn extension(&self) -> &'static str {
         match self {
-            ImageFormat::Jpeg => write!(f, "image/jpeg"),
-            ImageFormat::Png => write!(f, "image/png"),
-            ImageFormat::Gif => write!(f, "image/gif"),
-            ImageFormat::WebP => write!(f, "image/webp"),
-            ImageFormat::Bmp => write!(f, "image/bmp"),
-            ImageFormat::Unknown => write!(f, "application/octet-stream"),
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+            ImageFormat::Gif => "gif",
+            ImageFormat::WebP => "webp",
+            ImageFormat::Bmp => "bmp",
+            ImageFormat::Avif => "avif",
+            ImageFormat::Jxl => "jxl",
+            ImageFormat::Tiff => "tif",
+            ImageFormat::Unknown => "bin",
         }
     }
 }
 
-fn detect_image_format(data: &[u8]) -> Result<ImageFormat> {
+/// Bytes read from each entry to recognize its format.
+pub(crate) const SNIFF_LEN: u64 = 64;
+
+const IMAGE_EXTENSIONS: &[&str] = &[
+    ".jpg", ".jpeg", ".png", ".gif", ".webp", ".bmp", ".avif", ".jxl", ".tif", ".tiff",
+];
+
+pub(crate) fn has_image_extension(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    IMAGE_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+}
+
+fn sniff_entry<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    index: usize,
+    password: Option<&[u8]>,
+) -> Option<ImageFormat> {
+    let entry = match password {
+        Some(password) => archive.by_index_decrypt(index, password).ok()?,
+        None => archive.by_index(index).ok()?,
+    };
+    if entry.is_dir() {
+        return Some(ImageFormat::Unknown);
+    }
+    let mut header = Vec::with_capacity(SNIFF_LEN as usize);
+    entry.take(SNIFF_LEN).read_to_end(&mut header).ok()?;
+    Some(sniff(&header))
+}
+
+pub(crate) fn detect_image_format(data: &[u8]) -> Result<ImageFormat> {
     if data.len() < 4 {
         return Err(CbzError::InvalidImageFormat);
     }
+    Ok(sniff(data))
+}
+
+pub(crate) fn sniff(data: &[u8]) -> ImageFormat {
+    if data.len() < 4 {
+        return ImageFormat::Unknown;
+    }
 
     if data[0..2] == [0xFF, 0xD8] {
-        return Ok(ImageFormat::Jpeg);
+        return ImageFormat::Jpeg;
     }
 
     if data[0..4] == [0x89, b'P', b'N', b'G'] {
-        return Ok(ImageFormat::Png);
+        return ImageFormat::Png;
     }
 
     if data[0..4] == [b'G', b'I', b'F', b'8'] {
-        return Ok(ImageFormat::Gif);
+        return ImageFormat::Gif;
     }
 
     if data[0..4] == [b'R', b'I', b'F', b'F'] && data.len() >= 12 && &data[8..12] == b"WEBP" {
-        return Ok(ImageFormat::WebP);
+        return ImageFormat::WebP;
     }
 
     if data[0..2] == [b'B', b'M'] {
-        return Ok(ImageFormat::Bmp);
+        return ImageFormat::Bmp;
     }
 
-    Ok(ImageFormat::Unknown)
+    if data[0..4] == [b'I', b'I', b'*', 0] || data[0..4] == [b'M', b'M', 0, b'*'] {
+        return ImageFormat::Tiff;
+    }
+
+    // Bare codestream, or the ISOBMFF container.
+    if data[0..2] == [0xFF, 0x0A]
+        || data.starts_with(&[
+            0, 0, 0, 0x0C, b'J', b'X', b'L', b' ', 0x0D, 0x0A, 0x87, 0x0A,
+        ])
+    {
+        return ImageFormat::Jxl;
+    }
+
+    if is_avif(data) {
+        return ImageFormat::Avif;
+    }
+
+    ImageFormat::Unknown
+}
+
+/// Checks the `ftyp` box for an AVIF brand, major or compatible.
+fn is_avif(data: &[u8]) -> bool {
+    if data.len() < 12 || &data[4..8] != b"ftyp" {
+        return false;
+    }
+    let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let end = size.min(data.len());
+    // Major brand, then the compatible brands after the minor version.
+    std::iter::once(&data[8..12])
+        .chain(data.get(16..end).unwrap_or_default().chunks_exact(4))
+        .any(|brand| brand == b"avif" || brand == b"avis")
 }
 
 #[derive(Debug)]
@@ -142,14 +764,7 @@ pub struct ImageEntry {
 
 impl ImageEntry {
     pub fn mime_type(&self) -> &'static str {
-        match self.format {
-            ImageFormat::Jpeg => "image/jpeg",
-            ImageFormat::Png => "image/png",
-            ImageFormat::Gif => "image/gif",
-            ImageFormat::WebP => "image/webp",
-            ImageFormat::Bmp => "image/bmp",
-            ImageFormat::Unknown => "application/octet-stream",
-        }
+        self.format.mime_type()
     }
 
     pub fn into_stream(self) -> impl futures::Stream<Item = io::Result<Vec<u8>>> {
@@ -190,9 +805,10 @@ impl<R: Read + io::Seek> Iterator for ImageStream<R> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::{JPEG, create_cbz, page};
     use std::io::Write;
-    use zip::ZipWriter;
     use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
 
     fn create_test_cbz() -> Vec<u8> {
         let mut buffer = Vec::new();
@@ -215,6 +831,52 @@ mod tests {
         buffer
     }
 
+    fn write_test_cbz() -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&create_test_cbz()).unwrap();
+        file
+    }
+
+    fn read_all<R: Read + io::Seek>(archive: &mut CbzArchive<R>) -> Vec<(String, Vec<u8>)> {
+        archive
+            .image_names()
+            .into_iter()
+            .map(|name| {
+                let data = archive.read_entry(&name).unwrap();
+                (name, data)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_constructors_agree() {
+        let file = write_test_cbz();
+        let expected = read_all(&mut CbzArchive::from_bytes(create_test_cbz()).unwrap());
+        assert_eq!(expected.len(), 2);
+
+        let mut from_file = CbzArchive::from_file(file.path()).await.unwrap();
+        assert_eq!(read_all(&mut from_file), expected);
+
+        let mut opened = CbzArchive::open(file.path()).unwrap();
+        assert_eq!(opened.len(), 3);
+        assert_eq!(read_all(&mut opened), expected);
+
+        let mut mapped = unsafe { CbzArchive::open_mmap(file.path()).unwrap() };
+        assert_eq!(mapped.len(), 3);
+        assert_eq!(read_all(&mut mapped), expected);
+
+        let reader = File::open(file.path()).unwrap();
+        let mut from_reader = CbzArchive::from_reader(reader).unwrap();
+        assert_eq!(read_all(&mut from_reader), expected);
+    }
+
+    #[test]
+    fn test_open_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = CbzArchive::open(dir.path().join("missing.cbz"));
+        assert!(matches!(result, Err(CbzError::Io(_))));
+    }
+
     #[test]
     fn test_from_bytes() {
         let data = create_test_cbz();
@@ -232,6 +894,400 @@ mod tests {
         assert!(images.contains(&"page2.jpg".to_string()));
     }
 
+    #[test]
+    fn test_image_names_sniffed() {
+        let data = create_cbz(&[
+            ("page1", JPEG),
+            ("page2.txt", &[0x89, b'P', b'N', b'G']),
+            ("notes.jpg", b"not an image"),
+            ("dir/", b""),
+        ]);
+        let archive = CbzArchive::from_bytes(data).unwrap();
+        assert_eq!(archive.image_names(), ["page1", "page2.txt"]);
+    }
+
+    #[test]
+    fn test_chapters() {
+        let chapter = create_cbz(&[("002.jpg", JPEG), ("001.jpg", JPEG)]);
+        let data = create_cbz(&[
+            ("ch10/001.jpg", JPEG),
+            ("ch2.cbz", &chapter),
+            ("cover.jpg", JPEG),
+            ("ch1/002.jpg", JPEG),
+            ("ch1/001.jpg", JPEG),
+        ]);
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+
+        let chapters = archive.chapters();
+        let titles: Vec<&str> = chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, ["", "ch1", "ch2", "ch10"]);
+        assert_eq!(chapters[1].pages, ["ch1/001.jpg", "ch1/002.jpg"]);
+        assert_eq!(chapters[2].pages, ["ch2.cbz!/001.jpg", "ch2.cbz!/002.jpg"]);
+
+        assert_eq!(archive.pages().len(), 6);
+        let page = archive.read_image("ch2.cbz!/002.jpg").unwrap();
+        assert_eq!(page.name, "ch2.cbz!/002.jpg");
+        assert_eq!(page.format, ImageFormat::Jpeg);
+        assert!(archive.read_image("ch2.cbz!/003.jpg").is_err());
+    }
+
+    #[test]
+    fn test_nested_archive_cache() {
+        let chapter = create_cbz(&[("001.jpg", JPEG), ("002.jpg", JPEG)]);
+        let mut buffer = Vec::new();
+        let mut writer = ZipWriter::new(Cursor::new(&mut buffer));
+        for (name, method) in [
+            ("stored.cbz", CompressionMethod::Stored),
+            ("deflated.cbz", CompressionMethod::Deflated),
+        ] {
+            let options = SimpleFileOptions::default().compression_method(method);
+            writer.start_file(name, options).unwrap();
+            writer.write_all(&chapter).unwrap();
+        }
+        writer.finish().unwrap();
+        let mut archive = CbzArchive::from_bytes(buffer).unwrap();
+
+        // Stored archives are read in place, without a copy.
+        assert_eq!(archive.chapters().len(), 2);
+        archive.clear_cache();
+        let pages = archive.with_nested("stored.cbz", true, |n| Ok(n.own_pages()));
+        assert_eq!(pages.unwrap(), ["001.jpg", "002.jpg"]);
+        archive.read_image("stored.cbz!/002.jpg").unwrap();
+        archive.read_page_header("stored.cbz!/001.jpg", 4).unwrap();
+        assert!(archive.read_image("stored.cbz!/003.jpg").is_err());
+        assert_eq!(archive.cached_size(), 0);
+
+        // Compressed ones are decompressed once, and again after clearing.
+        archive.read_image("deflated.cbz!/001.jpg").unwrap();
+        assert_eq!(archive.cached_size(), chapter.len() as u64);
+        archive.clear_cache();
+        assert_eq!(archive.cached_size(), 0);
+        let page = archive.read_image("deflated.cbz!/002.jpg").unwrap();
+        assert_eq!(page.format, ImageFormat::Jpeg);
+        assert_eq!(archive.cached_size(), chapter.len() as u64);
+    }
+
+    /// A stored single-entry archive, and the offsets of its local and
+    /// central headers.
+    fn create_stored_cbz(name: &str, data: &[u8]) -> (Vec<u8>, usize, usize) {
+        let mut buffer = Vec::new();
+        let mut writer = ZipWriter::new(Cursor::new(&mut buffer));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file(name, options).unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap();
+
+        let find = |signature: &[u8]| buffer.windows(4).position(|w| w == signature).unwrap();
+        let local = find(b"PK\x03\x04");
+        let central = find(b"PK\x01\x02");
+        (buffer, local, central)
+    }
+
+    #[test]
+    fn test_entry_errors() {
+        let (mut data, local, _) = create_stored_cbz("page.jpg", &[0xFF, 0xD8, 0xFF, 0xE0]);
+        // Corrupt the last byte of the stored data, right after the name.
+        data[local + 30 + "page.jpg".len() + 3] ^= 0xFF;
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+        let images: Vec<_> = archive.iter_images().collect();
+        assert!(matches!(&images[..], [Err(CbzError::CrcMismatch(name))] if name == "page.jpg"));
+
+        let (mut data, local, central) = create_stored_cbz("page.jpg", JPEG);
+        // Compression method 97, WavPack.
+        data[local + 8..local + 10].copy_from_slice(&97u16.to_le_bytes());
+        data[central + 10..central + 12].copy_from_slice(&97u16.to_le_bytes());
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+        assert!(matches!(
+            archive.read_entry("page.jpg"),
+            Err(CbzError::UnsupportedCompression { .. })
+        ));
+
+        let (mut data, _, central) = create_stored_cbz("page.jpg", JPEG);
+        // General purpose flag bit 0, encrypted.
+        data[central + 8] |= 1;
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+        assert!(matches!(
+            archive.read_entry("page.jpg"),
+            Err(CbzError::PasswordRequired(_))
+        ));
+        assert!(matches!(
+            archive.read_entry("missing.jpg"),
+            Err(CbzError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_password() {
+        let mut buffer = Vec::new();
+        let mut writer = ZipWriter::new(Cursor::new(&mut buffer));
+        let options =
+            SimpleFileOptions::default().with_aes_encryption(zip::AesMode::Aes256, "secret");
+        writer.start_file("page.png", options).unwrap();
+        writer.write_all(&[0x89, 0x50, 0x4E, 0x47, 0, 0]).unwrap();
+        writer.finish().unwrap();
+
+        let mut archive = CbzArchive::from_bytes(buffer).unwrap();
+        assert!(archive.is_encrypted());
+        assert!(matches!(
+            archive.read_entry("page.png"),
+            Err(CbzError::PasswordRequired(_))
+        ));
+        assert!(matches!(
+            archive.set_password("wrong"),
+            Err(CbzError::WrongPassword(_))
+        ));
+        assert!(matches!(
+            archive.read_entry("page.png"),
+            Err(CbzError::PasswordRequired(_))
+        ));
+
+        archive.set_password("secret").unwrap();
+        let image = archive.read_image("page.png").unwrap();
+        assert_eq!(image.format, ImageFormat::Png);
+        assert!(archive.verify().is_healthy());
+    }
+
+    fn assert_pages_read_back(data: Vec<u8>, pages: &[(&str, Vec<u8>)]) {
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+        assert!(archive.verify().is_healthy());
+        let expected: Vec<(String, Vec<u8>)> = pages
+            .iter()
+            .map(|(name, data)| (name.to_string(), data.clone()))
+            .collect();
+        assert_eq!(read_all(&mut archive), expected);
+    }
+
+    #[test]
+    fn test_compression_methods() {
+        let pages = [("001.jpg", page(3)), ("002.jpg", page(5))];
+        for method in [
+            CompressionMethod::Stored,
+            CompressionMethod::Deflated,
+            #[cfg(feature = "bzip2")]
+            CompressionMethod::Bzip2,
+            #[cfg(feature = "xz")]
+            CompressionMethod::Xz,
+            #[cfg(feature = "zstd")]
+            CompressionMethod::Zstd,
+        ] {
+            let mut buffer = Vec::new();
+            let mut writer = ZipWriter::new(Cursor::new(&mut buffer));
+            let options = SimpleFileOptions::default().compression_method(method);
+            for (name, data) in &pages {
+                writer.start_file(*name, options).unwrap();
+                writer.write_all(data).unwrap();
+            }
+            writer.finish().unwrap();
+            assert_pages_read_back(buffer, &pages);
+        }
+    }
+
+    #[test]
+    fn test_compression_fixtures() {
+        // Written by other tools rather than the zip crate, which cannot
+        // write LZMA: lzma.cbz by Python's zipfile, the others by bsdtar
+        // 3.8 with `--format zip --options zip:compression=<method>`.
+        let pages = [("001.jpg", page(3)), ("002.jpg", page(4))];
+        for data in [
+            #[cfg(feature = "bzip2")]
+            &include_bytes!("../tests/data/bzip2.cbz")[..],
+            #[cfg(feature = "lzma")]
+            &include_bytes!("../tests/data/lzma.cbz")[..],
+            #[cfg(feature = "xz")]
+            &include_bytes!("../tests/data/xz.cbz")[..],
+            #[cfg(feature = "zstd")]
+            &include_bytes!("../tests/data/zstd.cbz")[..],
+        ] {
+            assert_pages_read_back(data.to_vec(), &pages);
+        }
+    }
+
+    #[test]
+    fn test_deflate64() {
+        // Deflate64 only differs from deflate in length code 285 and
+        // distance codes 30 and 31, which data this short never uses, so a
+        // deflate stream relabelled as deflate64 is a valid one. Only 7-Zip
+        // and Windows write deflate64, so there is no fixture made by them.
+        let mut buffer = Vec::new();
+        let mut writer = ZipWriter::new(Cursor::new(&mut buffer));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        writer.start_file("001.jpg", options).unwrap();
+        writer.write_all(&page(3)).unwrap();
+        writer.finish().unwrap();
+
+        let find = |signature: &[u8]| buffer.windows(4).position(|w| w == signature).unwrap();
+        let (local, central) = (find(b"PK\x03\x04"), find(b"PK\x01\x02"));
+        buffer[local + 8..local + 10].copy_from_slice(&9u16.to_le_bytes());
+        buffer[central + 10..central + 12].copy_from_slice(&9u16.to_le_bytes());
+
+        assert_pages_read_back(buffer, &[("001.jpg", page(3))]);
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut archive = CbzArchive::from_bytes(create_test_cbz()).unwrap();
+        let report = archive.verify();
+        assert_eq!(report.checked, 3);
+        assert!(report.is_healthy());
+
+        let (mut data, local, _) = create_stored_cbz("page.jpg", JPEG);
+        data[local + 30 + "page.jpg".len()] ^= 0xFF;
+        let report = CbzArchive::from_bytes(data).unwrap().verify();
+        assert_eq!(report.errors.len(), 1);
+        assert!(matches!(report.errors[0].error, CbzError::CrcMismatch(_)));
+    }
+
+    #[test]
+    fn test_skipped_entries() {
+        let data = create_cbz(&[
+            ("001.jpg", JPEG),
+            ("__MACOSX/._001.jpg", JPEG),
+            ("._002.jpg", JPEG),
+            (".hidden/003.jpg", JPEG),
+            ("004.jpg", b""),
+            ("cover/thumb.jpg", JPEG),
+            ("Thumbs.db", b"\xD0\xCF\x11\xE0"),
+        ]);
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+        archive.set_filter(FilterPolicy::default().with_glob("cover/**").unwrap());
+        assert_eq!(archive.image_names(), ["001.jpg"]);
+
+        let skipped: Vec<_> = archive
+            .skipped_entries()
+            .into_iter()
+            .map(|entry| (entry.name, entry.reason))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                ("__MACOSX/._001.jpg".to_string(), SkipReason::AppleDouble),
+                ("._002.jpg".to_string(), SkipReason::AppleDouble),
+                (".hidden/003.jpg".to_string(), SkipReason::Hidden),
+                ("004.jpg".to_string(), SkipReason::Empty),
+                (
+                    "cover/thumb.jpg".to_string(),
+                    SkipReason::Glob("cover/**".to_string())
+                ),
+                ("Thumbs.db".to_string(), SkipReason::NotAnImage),
+            ]
+        );
+
+        archive.set_filter(FilterPolicy::allow_all());
+        assert_eq!(archive.image_names().len(), 5);
+    }
+
+    #[test]
+    fn test_detect_modern_formats() {
+        let avif = b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf";
+        let avif_compatible = b"\0\0\0\x18ftypmif1\0\0\0\0mif1avif";
+        let heic = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic";
+        let jxl_container = b"\0\0\0\x0cJXL \r\n\x87\n\0\0\0\x14ftypjxl ";
+
+        assert_eq!(sniff(avif), ImageFormat::Avif);
+        assert_eq!(sniff(avif_compatible), ImageFormat::Avif);
+        assert_eq!(sniff(heic), ImageFormat::Unknown);
+        assert_eq!(sniff(&[0xFF, 0x0A, 0xFA, 0x3F]), ImageFormat::Jxl);
+        assert_eq!(sniff(jxl_container), ImageFormat::Jxl);
+        assert_eq!(sniff(b"II*\0\x08\0\0\0"), ImageFormat::Tiff);
+        assert_eq!(sniff(b"MM\0*\0\0\0\x08"), ImageFormat::Tiff);
+        assert_eq!(ImageFormat::Jxl.to_string(), "image/jxl");
+    }
+
+    #[test]
+    fn test_pages_natural_order() {
+        let data = create_cbz(&[
+            ("page10.jpg", JPEG),
+            ("page2.jpg", JPEG),
+            ("page1.jpg", JPEG),
+        ]);
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+        assert_eq!(archive.pages(), ["page1.jpg", "page2.jpg", "page10.jpg"]);
+    }
+
+    #[test]
+    fn test_pages_comic_info_order() {
+        let comic_info = br#"<?xml version="1.0"?>
+<ComicInfo>
+  <Pages>
+    <Page Image="2" Type="FrontCover" />
+    <Page Image="0" />
+  </Pages>
+</ComicInfo>"#;
+        let data = create_cbz(&[
+            ("ComicInfo.xml", comic_info),
+            ("page1.jpg", JPEG),
+            ("page2.jpg", JPEG),
+            ("page10.jpg", JPEG),
+        ]);
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+        assert_eq!(archive.pages(), ["page10.jpg", "page1.jpg", "page2.jpg"]);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let data = create_cbz(&[
+            (
+                "ComicInfo.xml",
+                b"<ComicInfo><Series>Yonde</Series><Manga>Yes</Manga></ComicInfo>",
+            ),
+            ("page1.jpg", JPEG),
+        ]);
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+        let info = archive.metadata().unwrap().unwrap();
+        assert_eq!(info.series.as_deref(), Some("Yonde"));
+        assert_eq!(info.manga, crate::Manga::Yes);
+
+        let mut archive = CbzArchive::from_bytes(create_test_cbz()).unwrap();
+        assert!(archive.metadata().unwrap().is_none());
+    }
+
+    fn create_raw_name_cbz(raw_name: &[u8]) -> Vec<u8> {
+        // The zip writer only takes UTF-8 names, so write a same-length
+        // placeholder and patch the name bytes in the local and central headers.
+        let placeholder = "x".repeat(raw_name.len());
+        let mut data = create_cbz(&[(&placeholder, JPEG)]);
+        let mut start = 0;
+        while let Some(offset) = data[start..]
+            .windows(raw_name.len())
+            .position(|w| w == placeholder.as_bytes())
+        {
+            let at = start + offset;
+            data[at..at + raw_name.len()].copy_from_slice(raw_name);
+            start = at + raw_name.len();
+        }
+        data
+    }
+
+    #[test]
+    fn test_shift_jis_names() {
+        // "漫画/001.jpg" in CP932.
+        let (raw, _, _) = encoding_rs::SHIFT_JIS.encode("漫画/001.jpg");
+        let data = create_raw_name_cbz(&raw);
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+
+        assert_eq!(archive.name_encoding(), NameEncoding::ShiftJis);
+        assert_eq!(archive.file_names(), ["漫画/001.jpg"]);
+        assert_eq!(archive.pages(), ["漫画/001.jpg"]);
+        assert_eq!(archive.read_entry("漫画/001.jpg").unwrap(), JPEG);
+
+        archive.set_name_encoding(NameEncoding::Cp437);
+        assert_ne!(archive.file_names(), ["漫画/001.jpg"]);
+        let name = archive.file_names().remove(0);
+        assert_eq!(archive.read_entry(&name).unwrap(), JPEG);
+    }
+
+    #[test]
+    fn test_utf8_names() {
+        let data = create_cbz(&[("漫画/001.jpg", JPEG)]);
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+        assert_eq!(archive.name_encoding(), NameEncoding::Utf8);
+        assert_eq!(archive.read_entry("漫画/001.jpg").unwrap(), JPEG);
+        assert!(matches!(
+            archive.read_entry("missing.jpg"),
+            Err(CbzError::NotFound(_))
+        ));
+    }
+
     #[test]
     fn test_read_image() {
         let data = create_test_cbz();
@@ -242,6 +1298,22 @@ mod tests {
         assert_eq!(image.format, ImageFormat::Png);
     }
 
+    #[test]
+    fn test_read_page_header() {
+        let data = create_test_cbz();
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+
+        let header = archive.read_page_header("page1.png", 4).unwrap();
+        assert_eq!(header.data, [0x89, b'P', b'N', b'G']);
+        assert_eq!(header.format, ImageFormat::Png);
+        let whole = archive.read_page_header("page2.jpg", 1024).unwrap();
+        assert_eq!(whole.data, [0xFF, 0xD8, 0xFF, 0xE0]);
+        assert!(matches!(
+            archive.read_entry_header("missing.jpg", 4),
+            Err(CbzError::NotFound(_))
+        ));
+    }
+
     #[test]
     fn test_iter_images() {
         let data = create_test_cbz();
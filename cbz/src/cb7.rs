@@ -0,0 +1,151 @@
+use crate::archive::{ImageEntry, sniff};
+use crate::comic_info::ComicInfo;
+use crate::error::Result;
+use crate::filter::FilterPolicy;
+use crate::source::{
+    ComicSource, SourceEntry, find_comic_info, image_entry, not_found, parse_comic_info,
+    source_pages,
+};
+use sevenz_rust::{Password, SevenZReader};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Cursor, Read, SeekFrom};
+use std::path::Path;
+
+/// A comic packed in a 7z archive (`.cb7`).
+///
+/// 7z archives are usually solid, so a single page cannot be decompressed
+/// without everything before it. The whole archive is decompressed into
+/// memory when it is opened instead.
+pub struct Cb7Archive {
+    entries: Vec<SourceEntry>,
+    data: Vec<Vec<u8>>,
+    indices: HashMap<String, usize>,
+    filter: FilterPolicy,
+    size: u64,
+}
+
+impl Cb7Archive {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        Self::from_reader(Cursor::new(data))
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_reader(File::open(path.as_ref())?)
+    }
+
+    pub fn from_reader<R: Read + io::Seek>(mut reader: R) -> Result<Self> {
+        let size = reader.seek(SeekFrom::End(0))?;
+        reader.rewind()?;
+
+        let mut archive = SevenZReader::new(reader, size, Password::empty())?;
+        let mut entries = Vec::new();
+        let mut data = Vec::new();
+        archive.for_each_entries(|entry, reader| {
+            if entry.is_directory() {
+                return Ok(true);
+            }
+            let mut buffer = Vec::with_capacity(entry.size() as usize);
+            reader.read_to_end(&mut buffer)?;
+            entries.push(SourceEntry {
+                name: entry.name().to_string(),
+                size: entry.size(),
+                format: Some(sniff(&buffer)),
+            });
+            data.push(buffer);
+            Ok(true)
+        })?;
+
+        let mut indices = HashMap::new();
+        for (index, entry) in entries.iter().enumerate() {
+            indices.entry(entry.name.clone()).or_insert(index);
+        }
+        Ok(Self {
+            entries,
+            data,
+            indices,
+            filter: FilterPolicy::default(),
+            size,
+        })
+    }
+
+    pub fn set_filter(&mut self, filter: FilterPolicy) {
+        self.filter = filter;
+    }
+
+    pub fn file_names(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect()
+    }
+
+    pub fn read_entry(&self, name: &str) -> Result<Vec<u8>> {
+        let index = *self.indices.get(name).ok_or_else(|| not_found(name))?;
+        Ok(self.data[index].clone())
+    }
+}
+
+impl ComicSource for Cb7Archive {
+    fn pages(&mut self) -> Vec<String> {
+        let info = self.metadata().ok().flatten();
+        source_pages(&self.entries, &self.filter, info.as_ref())
+    }
+
+    fn read_page(&mut self, name: &str) -> Result<ImageEntry> {
+        let data = self.read_entry(name)?;
+        image_entry(name, data)
+    }
+
+    fn metadata(&mut self) -> Result<Option<ComicInfo>> {
+        let names = self.entries.iter().map(|entry| &entry.name);
+        let Some(name) = find_comic_info(names).cloned() else {
+            return Ok(None);
+        };
+        let data = self.read_entry(&name)?;
+        parse_comic_info(&data).map(Some)
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Every entry, decompressed when the archive was opened. Clearing the
+    /// cache cannot free them.
+    fn cached_size(&self) -> u64 {
+        self.data.iter().map(|data| data.len() as u64).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sevenz_rust::{SevenZArchiveEntry, SevenZWriter};
+
+    fn create_cb7(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = SevenZWriter::new(Cursor::new(Vec::new())).unwrap();
+        for (name, data) in entries {
+            let mut entry = SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            writer.push_archive_entry(entry, Some(*data)).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_pages() {
+        let data = create_cb7(&[
+            ("page10.jpg", &[0xFF, 0xD8, 0xFF, 0xE0]),
+            ("page2.png", &[0x89, b'P', b'N', b'G']),
+            ("credits.txt", b"scanned by"),
+        ]);
+        let mut archive = Cb7Archive::from_bytes(data).unwrap();
+        assert_eq!(archive.pages(), ["page2.png", "page10.jpg"]);
+        assert_eq!(archive.cached_size(), 18);
+
+        let page = archive.read_page("page10.jpg").unwrap();
+        assert_eq!(page.mime_type(), "image/jpeg");
+        assert!(archive.read_page("missing.png").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::archive::{ImageEntry, SNIFF_LEN, sniff};
+use crate::comic_info::ComicInfo;
+use crate::error::Result;
+use crate::filter::FilterPolicy;
+use crate::source::{
+    ComicSource, SourceEntry, find_comic_info, image_entry, not_found, parse_comic_info,
+    source_pages,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, SeekFrom};
+use std::path::Path;
+
+/// A comic packed in an uncompressed tar archive (`.cbt`).
+///
+/// Entry offsets are recorded when it is opened, so pages are read with a
+/// single seek.
+pub struct CbtArchive<R: Read + io::Seek> {
+    reader: R,
+    entries: Vec<SourceEntry>,
+    // Where each entry's data starts, by entry index.
+    offsets: Vec<u64>,
+    indices: HashMap<String, usize>,
+    filter: FilterPolicy,
+    size: u64,
+}
+
+impl CbtArchive<Cursor<Vec<u8>>> {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        Self::from_reader(Cursor::new(data))
+    }
+}
+
+impl CbtArchive<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path.as_ref())?;
+        Self::from_reader(BufReader::new(file))
+    }
+}
+
+impl<R: Read + io::Seek> CbtArchive<R> {
+    pub fn from_reader(mut reader: R) -> Result<Self> {
+        let size = reader.seek(SeekFrom::End(0))?;
+        reader.rewind()?;
+
+        let mut archive = tar::Archive::new(reader);
+        let mut entries = Vec::new();
+        let mut offsets = Vec::new();
+        for entry in archive.entries_with_seek()? {
+            let entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+            let size = entry.size();
+            let offset = entry.raw_file_position();
+
+            let mut header = Vec::with_capacity(SNIFF_LEN as usize);
+            entry.take(SNIFF_LEN).read_to_end(&mut header)?;
+            entries.push(SourceEntry {
+                name,
+                size,
+                format: Some(sniff(&header)),
+            });
+            offsets.push(offset);
+        }
+
+        let mut indices = HashMap::new();
+        for (index, entry) in entries.iter().enumerate() {
+            indices.entry(entry.name.clone()).or_insert(index);
+        }
+        Ok(Self {
+            reader: archive.into_inner(),
+            entries,
+            offsets,
+            indices,
+            filter: FilterPolicy::default(),
+            size,
+        })
+    }
+
+    pub fn set_filter(&mut self, filter: FilterPolicy) {
+        self.filter = filter;
+    }
+
+    pub fn file_names(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect()
+    }
+
+    pub fn read_entry(&mut self, name: &str) -> Result<Vec<u8>> {
+        self.read_entry_header(name, u64::MAX)
+    }
+
+    /// Reads the first `len` bytes at most of an entry.
+    pub fn read_entry_header(&mut self, name: &str, len: u64) -> Result<Vec<u8>> {
+        let index = *self.indices.get(name).ok_or_else(|| not_found(name))?;
+        self.reader.seek(SeekFrom::Start(self.offsets[index]))?;
+        let mut buffer = vec![0; self.entries[index].size.min(len) as usize];
+        self.reader.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl<R: Read + io::Seek> ComicSource for CbtArchive<R> {
+    fn pages(&mut self) -> Vec<String> {
+        let info = self.metadata().ok().flatten();
+        source_pages(&self.entries, &self.filter, info.as_ref())
+    }
+
+    fn read_page(&mut self, name: &str) -> Result<ImageEntry> {
+        let data = self.read_entry(name)?;
+        image_entry(name, data)
+    }
+
+    fn read_page_header(&mut self, name: &str, len: u64) -> Result<ImageEntry> {
+        let data = self.read_entry_header(name, len)?;
+        image_entry(name, data)
+    }
+
+    fn metadata(&mut self) -> Result<Option<ComicInfo>> {
+        let names = self.entries.iter().map(|entry| &entry.name);
+        let Some(name) = find_comic_info(names).cloned() else {
+            return Ok(None);
+        };
+        let data = self.read_entry(&name)?;
+        parse_comic_info(&data).map(Some)
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Names are stored as given, which the tar crate's path setters would
+    /// not do for `./001.jpg`.
+    fn create_cbt(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_pages() {
+        let data = create_cbt(&[
+            ("vol/page10.jpg", &[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10]),
+            ("vol/page2.png", &[0x89, b'P', b'N', b'G']),
+            ("vol/notes.txt", b"hello"),
+        ]);
+        let mut archive = CbtArchive::from_bytes(data).unwrap();
+        assert_eq!(archive.pages(), ["vol/page2.png", "vol/page10.jpg"]);
+
+        let page = archive.read_page("vol/page2.png").unwrap();
+        assert_eq!(page.mime_type(), "image/png");
+        assert_eq!(page.data, [0x89, b'P', b'N', b'G']);
+        let header = archive.read_page_header("vol/page10.jpg", 4).unwrap();
+        assert_eq!(header.data, [0xFF, 0xD8, 0xFF, 0xE0]);
+        assert!(archive.read_page("missing.png").is_err());
+    }
+
+    #[test]
+    fn test_current_dir_names() {
+        // As `tar -cf comic.cbt .` stores them.
+        let data = create_cbt(&[
+            ("./002.jpg", &[0xFF, 0xD8, 0xFF, 0xE0]),
+            ("./001.jpg", &[0xFF, 0xD8, 0xFF, 0xE0]),
+            ("./.hidden.jpg", &[0xFF, 0xD8, 0xFF, 0xE0]),
+        ]);
+        let mut archive = CbtArchive::from_bytes(data).unwrap();
+        assert_eq!(archive.pages(), ["./001.jpg", "./002.jpg"]);
+        archive.read_page("./001.jpg").unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+use serde::Serialize;
+
+/// Joins the name of an archive nested in another to the name of a page
+/// inside it, as in `vol1/ch01.cbz!/001.jpg`.
+pub const NESTED_SEPARATOR: &str = "!/";
+
+/// A run of pages, from one subdirectory or one nested archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Chapter {
+    /// Name of the folder or archive, without its extension. Empty for pages
+    /// at the root.
+    pub title: String,
+    pub pages: Vec<String>,
+}
+
+/// Groups `pages` by parent directory, keeping their order, as
+/// `(directory, chapter)`.
+pub(crate) fn group_by_directory(pages: Vec<String>) -> Vec<(String, Chapter)> {
+    let mut groups: Vec<(String, Chapter)> = Vec::new();
+    for page in pages {
+        let directory = match page.rfind('/') {
+            Some(slash) => page[..slash].to_string(),
+            None => String::new(),
+        };
+        match groups.iter_mut().find(|(key, _)| *key == directory) {
+            Some((_, chapter)) => chapter.pages.push(page),
+            None => {
+                let title = directory.rsplit('/').next().unwrap_or_default().to_string();
+                groups.push((
+                    directory,
+                    Chapter {
+                        title,
+                        pages: vec![page],
+                    },
+                ));
+            }
+        }
+    }
+    groups
+}
+
+/// Title of the chapter held by the nested archive `name`.
+pub(crate) fn archive_title(name: &str) -> String {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    match file_name.rfind('.') {
+        Some(dot) if dot > 0 => file_name[..dot].to_string(),
+        _ => file_name.to_string(),
+    }
+}
+
+pub(crate) fn is_archive_name(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    lower.ends_with(".cbz") || lower.ends_with(".zip")
+}
+
+/// Splits a virtual page name into the nested archive and the page in it.
+pub(crate) fn split_nested(name: &str) -> Option<(&str, &str)> {
+    name.split_once(NESTED_SEPARATOR)
+}
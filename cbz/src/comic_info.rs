@@ -0,0 +1,328 @@
+use crate::error::{CbzError, Result};
+use quick_xml::events::{BytesDecl, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use serde::Serialize;
+
+pub const COMIC_INFO_NAME: &str = "ComicInfo.xml";
+
+/// Metadata from an archive's `ComicInfo.xml` (the ComicRack schema).
+///
+/// Only the fields yonde uses are read; unknown elements are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ComicInfo {
+    pub title: Option<String>,
+    pub series: Option<String>,
+    pub number: Option<String>,
+    pub volume: Option<i32>,
+    pub writer: Option<String>,
+    /// `LanguageISO`, e.g. `ja`.
+    pub language: Option<String>,
+    pub manga: Manga,
+    pub pages: Vec<ComicPage>,
+}
+
+impl ComicInfo {
+    pub fn parse(xml: &str) -> Result<Self> {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().trim_text(true);
+
+        let mut info = ComicInfo::default();
+        let mut current: Option<Vec<u8>> = None;
+        loop {
+            match reader.read_event().map_err(invalid)? {
+                Event::Start(e) if e.local_name().as_ref() == b"Page" => {
+                    info.pages.push(ComicPage::from_element(&e)?);
+                }
+                Event::Empty(e) if e.local_name().as_ref() == b"Page" => {
+                    info.pages.push(ComicPage::from_element(&e)?);
+                }
+                Event::Start(e) => current = Some(e.local_name().as_ref().to_vec()),
+                Event::End(_) => current = None,
+                Event::Text(text) => {
+                    let Some(element) = current.as_deref() else {
+                        continue;
+                    };
+                    let text = text.unescape().map_err(invalid)?.into_owned();
+                    match element {
+                        b"Title" => info.title = Some(text),
+                        b"Series" => info.series = Some(text),
+                        b"Number" => info.number = Some(text),
+                        b"Volume" => info.volume = text.parse().ok(),
+                        b"Writer" => info.writer = Some(text),
+                        b"LanguageISO" => info.language = Some(text),
+                        b"Manga" => info.manga = Manga::from_value(&text),
+                        _ => {}
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+
+    /// Serializes to a `ComicInfo.xml` document, leaving out unset fields.
+    pub fn to_xml(&self) -> String {
+        let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+        // Writing to a Vec cannot fail.
+        self.write(&mut writer).expect("write to Vec");
+        String::from_utf8(writer.into_inner()).expect("quick-xml writes UTF-8")
+    }
+
+    fn write(&self, writer: &mut Writer<Vec<u8>>) -> std::io::Result<()> {
+        writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("utf-8"), None)))?;
+        let volume = self.volume.map(|volume| volume.to_string());
+        let fields = [
+            ("Title", self.title.as_deref()),
+            ("Series", self.series.as_deref()),
+            ("Number", self.number.as_deref()),
+            ("Volume", volume.as_deref()),
+            ("Writer", self.writer.as_deref()),
+            ("LanguageISO", self.language.as_deref()),
+            (
+                "Manga",
+                (self.manga != Manga::Unknown).then(|| self.manga.as_str()),
+            ),
+        ];
+        writer
+            .create_element("ComicInfo")
+            .with_attribute(("xmlns:xsi", "http://www.w3.org/2001/XMLSchema-instance"))
+            .write_inner_content(|writer| {
+                for (element, value) in fields {
+                    if let Some(value) = value {
+                        writer
+                            .create_element(element)
+                            .write_text_content(BytesText::new(value))?;
+                    }
+                }
+                if self.pages.is_empty() {
+                    return Ok(());
+                }
+                writer
+                    .create_element("Pages")
+                    .write_inner_content(|writer| {
+                        for page in &self.pages {
+                            page.write(writer)?;
+                        }
+                        Ok(())
+                    })?;
+                Ok(())
+            })?;
+        Ok(())
+    }
+
+    /// Whether pages are read right to left.
+    pub fn is_right_to_left(&self) -> bool {
+        self.manga == Manga::YesAndRightToLeft
+    }
+
+    /// Pages carrying a `Bookmark` label, as `(image index, label)`.
+    pub fn bookmarks(&self) -> Vec<(usize, &str)> {
+        self.pages
+            .iter()
+            .filter_map(|page| Some((page.image, page.bookmark.as_deref()?)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum Manga {
+    #[default]
+    Unknown,
+    No,
+    Yes,
+    YesAndRightToLeft,
+}
+
+impl Manga {
+    fn as_str(self) -> &'static str {
+        match self {
+            Manga::Unknown => "Unknown",
+            Manga::No => "No",
+            Manga::Yes => "Yes",
+            Manga::YesAndRightToLeft => "YesAndRightToLeft",
+        }
+    }
+
+    fn from_value(value: &str) -> Self {
+        match value {
+            "No" => Manga::No,
+            "Yes" => Manga::Yes,
+            "YesAndRightToLeft" => Manga::YesAndRightToLeft,
+            _ => Manga::Unknown,
+        }
+    }
+}
+
+/// One `<Page>` of `<Pages>`; `image` indexes the archive's images.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ComicPage {
+    pub image: usize,
+    pub page_type: PageType,
+    /// Set on double spreads, which span two facing pages.
+    pub double_page: bool,
+    pub bookmark: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl ComicPage {
+    fn from_element(element: &BytesStart<'_>) -> Result<Self> {
+        let mut page = ComicPage::default();
+        let mut image = None;
+        for attr in element.attributes() {
+            let attr = attr.map_err(invalid)?;
+            let value = attr.unescape_value().map_err(invalid)?;
+            let value = value.trim();
+            match attr.key.local_name().as_ref() {
+                b"Image" => image = value.parse().ok(),
+                b"Type" => page.page_type = PageType::from_value(value),
+                b"DoublePage" => page.double_page = value.eq_ignore_ascii_case("true"),
+                b"Bookmark" if !value.is_empty() => page.bookmark = Some(value.to_string()),
+                b"ImageWidth" => page.width = value.parse().ok(),
+                b"ImageHeight" => page.height = value.parse().ok(),
+                _ => {}
+            }
+        }
+        page.image =
+            image.ok_or_else(|| CbzError::InvalidComicInfo("Page without Image".to_string()))?;
+        Ok(page)
+    }
+
+    fn write(&self, writer: &mut Writer<Vec<u8>>) -> std::io::Result<()> {
+        let image = self.image.to_string();
+        let width = self.width.map(|width| width.to_string());
+        let height = self.height.map(|height| height.to_string());
+        let mut element = writer
+            .create_element("Page")
+            .with_attribute(("Image", image.as_str()));
+        if self.page_type != PageType::Story {
+            element = element.with_attribute(("Type", self.page_type.as_str()));
+        }
+        if self.double_page {
+            element = element.with_attribute(("DoublePage", "true"));
+        }
+        if let Some(bookmark) = &self.bookmark {
+            element = element.with_attribute(("Bookmark", bookmark.as_str()));
+        }
+        if let Some(width) = &width {
+            element = element.with_attribute(("ImageWidth", width.as_str()));
+        }
+        if let Some(height) = &height {
+            element = element.with_attribute(("ImageHeight", height.as_str()));
+        }
+        element.write_empty()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum PageType {
+    FrontCover,
+    InnerCover,
+    Roundup,
+    #[default]
+    Story,
+    Advertisement,
+    Editorial,
+    Letters,
+    Preview,
+    BackCover,
+    Other,
+    Deleted,
+}
+
+impl PageType {
+    fn as_str(self) -> &'static str {
+        match self {
+            PageType::FrontCover => "FrontCover",
+            PageType::InnerCover => "InnerCover",
+            PageType::Roundup => "Roundup",
+            PageType::Story => "Story",
+            PageType::Advertisement => "Advertisement",
+            PageType::Editorial => "Editorial",
+            PageType::Letters => "Letters",
+            PageType::Preview => "Preview",
+            PageType::BackCover => "BackCover",
+            PageType::Other => "Other",
+            PageType::Deleted => "Deleted",
+        }
+    }
+
+    fn from_value(value: &str) -> Self {
+        match value {
+            "FrontCover" => PageType::FrontCover,
+            "InnerCover" => PageType::InnerCover,
+            "Roundup" => PageType::Roundup,
+            "Story" => PageType::Story,
+            "Advertisement" => PageType::Advertisement,
+            "Editorial" => PageType::Editorial,
+            "Letters" => PageType::Letters,
+            "Preview" => PageType::Preview,
+            "BackCover" => PageType::BackCover,
+            "Deleted" => PageType::Deleted,
+            _ => PageType::Other,
+        }
+    }
+}
+
+fn invalid(e: impl std::fmt::Display) -> CbzError {
+    CbzError::InvalidComicInfo(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Title>始まりの日</Title>
+  <Series>よんで &amp; 読んで</Series>
+  <Number>3</Number>
+  <Volume>1</Volume>
+  <Writer>Someone</Writer>
+  <LanguageISO>ja</LanguageISO>
+  <Manga>YesAndRightToLeft</Manga>
+  <Pages>
+    <Page Image="0" Type="FrontCover" ImageWidth="1000" ImageHeight="1500" />
+    <Page Image="1" Bookmark="Chapter 1" />
+    <Page Image="2" DoublePage="True"></Page>
+  </Pages>
+</ComicInfo>"#;
+
+    #[test]
+    fn test_parse() {
+        let info = ComicInfo::parse(SAMPLE).unwrap();
+        assert_eq!(info.title.as_deref(), Some("始まりの日"));
+        assert_eq!(info.series.as_deref(), Some("よんで & 読んで"));
+        assert_eq!(info.number.as_deref(), Some("3"));
+        assert_eq!(info.volume, Some(1));
+        assert_eq!(info.writer.as_deref(), Some("Someone"));
+        assert_eq!(info.language.as_deref(), Some("ja"));
+        assert!(info.is_right_to_left());
+
+        assert_eq!(info.pages.len(), 3);
+        assert_eq!(info.pages[0].page_type, PageType::FrontCover);
+        assert_eq!(info.pages[0].width, Some(1000));
+        assert_eq!(info.pages[1].page_type, PageType::Story);
+        assert!(info.pages[2].double_page);
+        assert_eq!(info.bookmarks(), [(1, "Chapter 1")]);
+    }
+
+    #[test]
+    fn test_to_xml_round_trip() {
+        let info = ComicInfo::parse(SAMPLE).unwrap();
+        let xml = info.to_xml();
+        assert!(xml.contains("<Series>よんで &amp; 読んで</Series>"));
+        assert_eq!(ComicInfo::parse(&xml).unwrap(), info);
+        assert_eq!(
+            ComicInfo::parse(&ComicInfo::default().to_xml()).unwrap(),
+            ComicInfo::default()
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(ComicInfo::parse("<ComicInfo><Pages><Page Type=\"Story\"/></Pages>").is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::archive::{ImageEntry, SNIFF_LEN, sniff};
+use crate::comic_info::ComicInfo;
+use crate::error::Result;
+use crate::filter::FilterPolicy;
+use crate::source::{
+    ComicSource, SourceEntry, find_comic_info, image_entry, not_found, parse_comic_info,
+    source_pages,
+};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// A folder of images, such as an unpacked scanlation release.
+///
+/// Subfolders are included, with entry names relative to the root and
+/// separated by `/` like archive entries.
+pub struct ComicDirectory {
+    root: PathBuf,
+    entries: Vec<SourceEntry>,
+    indices: HashMap<String, usize>,
+    filter: FilterPolicy,
+}
+
+impl ComicDirectory {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let root = path.as_ref().to_path_buf();
+        let mut entries = Vec::new();
+        walk(&root, "", &mut entries)?;
+
+        let indices = entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (entry.name.clone(), index))
+            .collect();
+        Ok(Self {
+            root,
+            entries,
+            indices,
+            filter: FilterPolicy::default(),
+        })
+    }
+
+    pub fn set_filter(&mut self, filter: FilterPolicy) {
+        self.filter = filter;
+    }
+
+    pub fn file_names(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect()
+    }
+
+    /// Reads a file listed when the folder was opened; other paths, such as
+    /// ones escaping the folder, are not found.
+    pub fn read_entry(&self, name: &str) -> Result<Vec<u8>> {
+        if !self.indices.contains_key(name) {
+            return Err(not_found(name));
+        }
+        Ok(fs::read(self.root.join(name))?)
+    }
+
+    /// Reads the first `len` bytes at most of a file, see
+    /// [`Self::read_entry`].
+    pub fn read_entry_header(&self, name: &str, len: u64) -> Result<Vec<u8>> {
+        if !self.indices.contains_key(name) {
+            return Err(not_found(name));
+        }
+        let mut header = Vec::new();
+        File::open(self.root.join(name))?
+            .take(len)
+            .read_to_end(&mut header)?;
+        Ok(header)
+    }
+}
+
+fn walk(dir: &Path, prefix: &str, entries: &mut Vec<SourceEntry>) -> Result<()> {
+    for dir_entry in fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        let file_type = dir_entry.file_type()?;
+        let name = format!("{}{}", prefix, dir_entry.file_name().to_string_lossy());
+        if file_type.is_dir() {
+            walk(&dir_entry.path(), &format!("{}/", name), entries)?;
+        } else if file_type.is_file() {
+            let mut header = Vec::with_capacity(SNIFF_LEN as usize);
+            let format = File::open(dir_entry.path())
+                .and_then(|file| file.take(SNIFF_LEN).read_to_end(&mut header))
+                .ok()
+                .map(|_| sniff(&header));
+            entries.push(SourceEntry {
+                name,
+                size: dir_entry.metadata()?.len(),
+                format,
+            });
+        }
+    }
+    Ok(())
+}
+
+impl ComicSource for ComicDirectory {
+    fn pages(&mut self) -> Vec<String> {
+        let info = self.metadata().ok().flatten();
+        source_pages(&self.entries, &self.filter, info.as_ref())
+    }
+
+    fn read_page(&mut self, name: &str) -> Result<ImageEntry> {
+        let data = self.read_entry(name)?;
+        image_entry(name, data)
+    }
+
+    fn read_page_header(&mut self, name: &str, len: u64) -> Result<ImageEntry> {
+        let data = self.read_entry_header(name, len)?;
+        image_entry(name, data)
+    }
+
+    fn metadata(&mut self) -> Result<Option<ComicInfo>> {
+        let names = self.entries.iter().map(|entry| &entry.name);
+        let Some(name) = find_comic_info(names).cloned() else {
+            return Ok(None);
+        };
+        let data = self.read_entry(&name)?;
+        parse_comic_info(&data).map(Some)
+    }
+
+    fn size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("ch2")).unwrap();
+        fs::write(dir.path().join("ch2/001.jpg"), [0xFF, 0xD8, 0xFF, 0xE0]).unwrap();
+        fs::write(dir.path().join("ch10.png"), [0x89, b'P', b'N', b'G']).unwrap();
+        fs::write(dir.path().join(".DS_Store"), [0, 0, 0, 1]).unwrap();
+
+        let mut source = ComicDirectory::open(dir.path()).unwrap();
+        assert_eq!(source.pages(), ["ch2/001.jpg", "ch10.png"]);
+        assert_eq!(source.size(), 12);
+
+        let page = source.read_page("ch2/001.jpg").unwrap();
+        assert_eq!(page.mime_type(), "image/jpeg");
+        assert!(source.read_page("../outside.jpg").is_err());
+    }
+}
@@ -0,0 +1,375 @@
+use crate::archive::{CbzArchive, ImageEntry};
+use crate::comic_info::{ComicInfo, Manga};
+use crate::error::{CbzError, Result};
+use crate::source::ComicSource;
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read};
+use std::path::Path;
+
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+
+/// A fixed-layout EPUB, read as a comic.
+///
+/// Pages are the images of the OPF spine items in spine order: an image item
+/// is a page itself, an XHTML item contributes the first `<img>` or SVG
+/// `<image>` it wraps. The OPF metadata and `page-progression-direction`
+/// are exposed as a synthesized [`ComicInfo`].
+pub struct EpubArchive<R: Read + io::Seek> {
+    archive: CbzArchive<R>,
+    pages: Vec<String>,
+    info: ComicInfo,
+}
+
+impl EpubArchive<Cursor<Vec<u8>>> {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        Self::from_reader(Cursor::new(data))
+    }
+}
+
+impl EpubArchive<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path.as_ref())?;
+        Self::from_reader(BufReader::new(file))
+    }
+}
+
+impl<R: Read + io::Seek> EpubArchive<R> {
+    pub fn from_reader(reader: R) -> Result<Self> {
+        let mut archive = CbzArchive::from_reader(reader)?;
+
+        let container = read_text(&mut archive, CONTAINER_PATH)?;
+        let opf_path = rootfile(&container)?;
+        let opf = parse_opf(&read_text(&mut archive, &opf_path)?)?;
+
+        let mut pages = Vec::with_capacity(opf.spine.len());
+        for idref in &opf.spine {
+            let Some(item) = opf.manifest.get(idref) else {
+                continue;
+            };
+            let href = resolve(&opf_path, &item.href);
+            if item.media_type.starts_with("image/") {
+                pages.push(href);
+            } else if let Some(src) = first_image(&read_text(&mut archive, &href)?)? {
+                pages.push(resolve(&href, &src));
+            }
+        }
+
+        let info = ComicInfo {
+            title: opf.title,
+            writer: opf.creator,
+            language: opf.language,
+            manga: if opf.right_to_left {
+                Manga::YesAndRightToLeft
+            } else {
+                Manga::Unknown
+            },
+            ..ComicInfo::default()
+        };
+        Ok(Self {
+            archive,
+            pages,
+            info,
+        })
+    }
+
+    /// Whether the spine's `page-progression-direction` is `rtl`.
+    pub fn is_right_to_left(&self) -> bool {
+        self.info.is_right_to_left()
+    }
+}
+
+impl<R: Read + io::Seek> ComicSource for EpubArchive<R> {
+    fn pages(&mut self) -> Vec<String> {
+        self.pages.clone()
+    }
+
+    fn read_page(&mut self, name: &str) -> Result<ImageEntry> {
+        self.archive.read_image(name)
+    }
+
+    fn read_page_header(&mut self, name: &str, len: u64) -> Result<ImageEntry> {
+        self.archive.read_page_header(name, len)
+    }
+
+    fn metadata(&mut self) -> Result<Option<ComicInfo>> {
+        Ok(Some(self.info.clone()))
+    }
+
+    fn size(&self) -> u64 {
+        ComicSource::size(&self.archive)
+    }
+}
+
+struct ManifestItem {
+    href: String,
+    media_type: String,
+}
+
+#[derive(Default)]
+struct Opf {
+    manifest: HashMap<String, ManifestItem>,
+    // Manifest ids, in reading order.
+    spine: Vec<String>,
+    right_to_left: bool,
+    title: Option<String>,
+    creator: Option<String>,
+    language: Option<String>,
+}
+
+fn read_text<R: Read + io::Seek>(archive: &mut CbzArchive<R>, name: &str) -> Result<String> {
+    let data = archive
+        .read_entry(name)
+        .map_err(|e| invalid(format!("cannot read {}: {}", name, e)))?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// Path of the OPF package document, from `container.xml`.
+fn rootfile(container: &str) -> Result<String> {
+    let mut reader = Reader::from_str(container);
+    loop {
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"rootfile" => {
+                if let Some(path) = attribute(&e, b"full-path")? {
+                    return Ok(path);
+                }
+            }
+            Event::Eof => return Err(invalid("container.xml has no rootfile")),
+            _ => {}
+        }
+    }
+}
+
+fn parse_opf(xml: &str) -> Result<Opf> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut opf = Opf::default();
+    let mut current: Option<Vec<u8>> = None;
+    loop {
+        let event = reader.read_event().map_err(invalid)?;
+        let is_start = matches!(event, Event::Start(_));
+        match event {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"item" => {
+                    let id = attribute(&e, b"id")?;
+                    let href = attribute(&e, b"href")?;
+                    if let (Some(id), Some(href)) = (id, href) {
+                        let media_type = attribute(&e, b"media-type")?.unwrap_or_default();
+                        opf.manifest.insert(id, ManifestItem { href, media_type });
+                    }
+                }
+                b"spine" => {
+                    opf.right_to_left =
+                        attribute(&e, b"page-progression-direction")?.as_deref() == Some("rtl");
+                }
+                b"itemref" => {
+                    // Non-linear items, such as pop-up notes, are not pages.
+                    let linear = attribute(&e, b"linear")?;
+                    if let Some(idref) = attribute(&e, b"idref")?
+                        && linear.as_deref() != Some("no")
+                    {
+                        opf.spine.push(idref);
+                    }
+                }
+                name => current = is_start.then(|| name.to_vec()),
+            },
+            Event::End(_) => current = None,
+            Event::Text(text) => {
+                let text = text.unescape().map_err(invalid)?.into_owned();
+                let field = match current.as_deref() {
+                    Some(b"title") => &mut opf.title,
+                    Some(b"creator") => &mut opf.creator,
+                    Some(b"language") => &mut opf.language,
+                    _ => continue,
+                };
+                // The first one is the main title or author.
+                field.get_or_insert(text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(opf)
+}
+
+/// The `src` of the first `<img>`, or `href` of the first SVG `<image>`.
+fn first_image(xhtml: &str) -> Result<Option<String>> {
+    let mut reader = Reader::from_str(xhtml);
+    reader.config_mut().check_end_names = false;
+    loop {
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"img" => return attribute(&e, b"src"),
+                // `xlink:href` in SVG 1.1, plain `href` in SVG 2.
+                b"image" => return attribute(&e, b"href"),
+                _ => {}
+            },
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// Value of the attribute with local name `name`, ignoring its prefix.
+fn attribute(element: &BytesStart<'_>, name: &[u8]) -> Result<Option<String>> {
+    for attr in element.attributes() {
+        let attr = attr.map_err(invalid)?;
+        if attr.key.local_name().as_ref() == name {
+            let value = attr.unescape_value().map_err(invalid)?;
+            return Ok(Some(value.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// Resolves `href` against the document at `base`, giving an entry name.
+fn resolve(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let href = percent_decode(href);
+
+    let mut parts: Vec<&str> = match base.rfind('/') {
+        Some(slash) => base[..slash].split('/').collect(),
+        None => Vec::new(),
+    };
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn invalid(e: impl std::fmt::Display) -> CbzError {
+    CbzError::InvalidEpub(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    const CONTAINER: &str = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="item/standard.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+
+    const OPF: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>よんで 第1巻</dc:title>
+    <dc:creator>Someone</dc:creator>
+    <dc:language>ja</dc:language>
+  </metadata>
+  <manifest>
+    <item id="p2" href="xhtml/p-002.xhtml" media-type="application/xhtml+xml"/>
+    <item id="p1" href="xhtml/p-001.xhtml" media-type="application/xhtml+xml"/>
+    <item id="cover" href="image/cover.jpg" media-type="image/jpeg"/>
+    <item id="i1" href="image/i-001.jpg" media-type="image/jpeg"/>
+    <item id="i2" href="image/i%20002.jpg" media-type="image/jpeg"/>
+  </manifest>
+  <spine page-progression-direction="rtl">
+    <itemref idref="cover"/>
+    <itemref idref="p1"/>
+    <itemref idref="p2" properties="page-spread-left"/>
+  </spine>
+</package>"#;
+
+    const PAGE_IMG: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>1</title></head>
+<body><div><img src="../image/i-001.jpg" alt=""/></div></body></html>"#;
+
+    const PAGE_SVG: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:xlink="http://www.w3.org/1999/xlink">
+<body><svg viewBox="0 0 100 150"><image width="100" height="150" xlink:href="../image/i%20002.jpg"/></svg></body></html>"#;
+
+    fn create_epub() -> Vec<u8> {
+        let jpeg: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0];
+        let entries: &[(&str, &[u8])] = &[
+            ("mimetype", b"application/epub+zip"),
+            ("META-INF/container.xml", CONTAINER.as_bytes()),
+            ("item/standard.opf", OPF.as_bytes()),
+            ("item/xhtml/p-001.xhtml", PAGE_IMG.as_bytes()),
+            ("item/xhtml/p-002.xhtml", PAGE_SVG.as_bytes()),
+            ("item/image/cover.jpg", jpeg),
+            ("item/image/i-001.jpg", jpeg),
+            ("item/image/i 002.jpg", jpeg),
+        ];
+
+        let mut buffer = Vec::new();
+        let mut writer = ZipWriter::new(Cursor::new(&mut buffer));
+        for (name, data) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_spine_pages() {
+        let mut epub = EpubArchive::from_bytes(create_epub()).unwrap();
+        assert_eq!(
+            epub.pages(),
+            [
+                "item/image/cover.jpg",
+                "item/image/i-001.jpg",
+                "item/image/i 002.jpg"
+            ]
+        );
+        assert_eq!(
+            epub.read_page("item/image/i 002.jpg").unwrap().mime_type(),
+            "image/jpeg"
+        );
+
+        assert!(epub.is_right_to_left());
+        let info = epub.metadata().unwrap().unwrap();
+        assert_eq!(info.title.as_deref(), Some("よんで 第1巻"));
+        assert_eq!(info.writer.as_deref(), Some("Someone"));
+        assert_eq!(info.manga, Manga::YesAndRightToLeft);
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            resolve("OEBPS/text/p1.xhtml", "../img/a.jpg"),
+            "OEBPS/img/a.jpg"
+        );
+        assert_eq!(resolve("content.opf", "img/a%2Bb.png#frag"), "img/a+b.png");
+    }
+}
@@ -1,5 +1,6 @@
 use std::io;
 use thiserror::Error;
+use zip::CompressionMethod;
 use zip::result::ZipError;
 
 #[derive(Error, Debug)]
@@ -10,11 +11,110 @@ pub enum CbzError {
     #[error("Zip error: {0}")]
     Zip(#[from] ZipError),
 
+    #[error("7z error: {0}")]
+    SevenZ(#[from] sevenz_rust::Error),
+
     #[error("Invalid image format")]
     InvalidImageFormat,
 
     #[error("Entry not found: {0}")]
     NotFound(String),
+
+    #[error("CRC mismatch in {0}")]
+    CrcMismatch(String),
+
+    #[error("Unsupported compression {method} in {name}")]
+    UnsupportedCompression { name: String, method: String },
+
+    #[error("Password required for {0}")]
+    PasswordRequired(String),
+
+    #[error("Wrong password for {0}")]
+    WrongPassword(String),
+
+    #[error("Encrypted entry cannot be recovered: {0}")]
+    Encrypted(String),
+
+    #[error("Duplicate entry: {0}")]
+    DuplicateEntry(String),
+
+    #[error("Archive is truncated in {0}")]
+    Truncated(String),
+
+    #[error("Invalid ComicInfo.xml: {0}")]
+    InvalidComicInfo(String),
+
+    #[error("Invalid OCR sidecar: {0}")]
+    InvalidOcrSidecar(String),
+
+    #[error("Entry name leaves the output folder: {0}")]
+    UnsafeName(String),
+
+    #[error("Invalid Mokuro file: {0}")]
+    InvalidMokuro(String),
+
+    #[error("Invalid EPUB: {0}")]
+    InvalidEpub(String),
+
+    #[error("Failed to transcode {name}: {reason}")]
+    Transcode { name: String, reason: String },
+
+    #[error("Invalid filter pattern: {0}")]
+    InvalidPattern(#[from] globset::Error),
 }
 
 pub type Result<T> = std::result::Result<T, CbzError>;
+
+impl CbzError {
+    /// Whether opening a zip failed because its central directory is missing
+    /// or cut off, as in a partial download, which
+    /// [`CbzArchive::recover`](crate::CbzArchive::recover) may salvage, rather
+    /// than because of unsupported features or failing to read the file.
+    pub fn is_damaged_archive(&self) -> bool {
+        match self {
+            CbzError::Truncated(_) | CbzError::Zip(ZipError::InvalidArchive(_)) => true,
+            CbzError::Zip(ZipError::Io(e)) | CbzError::Io(e) => {
+                e.kind() == io::ErrorKind::UnexpectedEof
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Gives a precise variant to an error from reading the entry `name`.
+pub(crate) fn entry_error(name: &str, method: CompressionMethod, err: CbzError) -> CbzError {
+    match err {
+        CbzError::Zip(ZipError::FileNotFound) => CbzError::NotFound(name.to_string()),
+        CbzError::Zip(ZipError::InvalidPassword) => CbzError::WrongPassword(name.to_string()),
+        // Raised by `read_index` with the name as stored in the archive.
+        CbzError::CrcMismatch(_) => CbzError::CrcMismatch(name.to_string()),
+        CbzError::Zip(ZipError::UnsupportedArchive(_)) if !is_supported(method) => {
+            CbzError::UnsupportedCompression {
+                name: name.to_string(),
+                method: method.to_string(),
+            }
+        }
+        CbzError::Zip(ZipError::Io(e)) | CbzError::Io(e) => match e.kind() {
+            io::ErrorKind::UnexpectedEof => CbzError::Truncated(name.to_string()),
+            _ => CbzError::Io(e),
+        },
+        other => other,
+    }
+}
+
+fn is_supported(method: CompressionMethod) -> bool {
+    match method {
+        CompressionMethod::Stored | CompressionMethod::Deflated => true,
+        #[cfg(feature = "bzip2")]
+        CompressionMethod::Bzip2 => true,
+        #[cfg(feature = "deflate64")]
+        CompressionMethod::Deflate64 => true,
+        #[cfg(feature = "lzma")]
+        CompressionMethod::Lzma => true,
+        #[cfg(feature = "xz")]
+        CompressionMethod::Xz => true,
+        #[cfg(feature = "zstd")]
+        CompressionMethod::Zstd => true,
+        _ => false,
+    }
+}
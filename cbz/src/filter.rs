@@ -0,0 +1,123 @@
+use crate::error::Result;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Decides which entries of an archive are junk rather than pages.
+///
+/// The default policy skips AppleDouble forks (`__MACOSX/`, `._*`), hidden
+/// files and zero-byte entries. Extra glob rules, such as `**/cover/**` for
+/// thumbnail folders, can be added with [`FilterPolicy::with_glob`].
+#[derive(Debug, Clone)]
+pub struct FilterPolicy {
+    pub skip_apple_double: bool,
+    pub skip_hidden: bool,
+    pub skip_empty: bool,
+    patterns: Vec<String>,
+    globs: GlobSet,
+}
+
+impl Default for FilterPolicy {
+    fn default() -> Self {
+        Self {
+            skip_apple_double: true,
+            skip_hidden: true,
+            skip_empty: true,
+            patterns: Vec::new(),
+            globs: GlobSet::empty(),
+        }
+    }
+}
+
+impl FilterPolicy {
+    /// A policy that keeps every image.
+    pub fn allow_all() -> Self {
+        Self {
+            skip_apple_double: false,
+            skip_hidden: false,
+            skip_empty: false,
+            ..Self::default()
+        }
+    }
+
+    /// Also skips entries whose path matches `pattern`.
+    ///
+    /// Matching is case-insensitive and `*` does not cross `/`, so use `**/`
+    /// to match in any folder.
+    pub fn with_glob(mut self, pattern: &str) -> Result<Self> {
+        self.patterns.push(pattern.to_string());
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &self.patterns {
+            builder.add(
+                GlobBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .literal_separator(true)
+                    .build()?,
+            );
+        }
+        self.globs = builder.build()?;
+        Ok(self)
+    }
+
+    pub fn globs(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub(crate) fn check(&self, name: &str, size: u64) -> Option<SkipReason> {
+        // `./` as in `./001.jpg`, common in tar archives, is not a dot folder.
+        let components: Vec<&str> = name
+            .split(['/', '\\'])
+            .filter(|c| !c.is_empty() && *c != ".")
+            .collect();
+        let file_name = components.last().copied().unwrap_or_default();
+
+        if self.skip_apple_double
+            && (file_name.starts_with("._") || components.contains(&"__MACOSX"))
+        {
+            return Some(SkipReason::AppleDouble);
+        }
+        if self.skip_hidden && components.iter().any(|c| c.starts_with('.')) {
+            return Some(SkipReason::Hidden);
+        }
+        if self.skip_empty && size == 0 {
+            return Some(SkipReason::Empty);
+        }
+        if let Some(&index) = self.globs.matches(name).first() {
+            return Some(SkipReason::Glob(self.patterns[index].clone()));
+        }
+        None
+    }
+}
+
+/// Why an entry is not a page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// A macOS resource fork, under `__MACOSX/` or named `._*`.
+    AppleDouble,
+    /// A dotfile, or inside a dot folder.
+    Hidden,
+    Empty,
+    /// Matched the user glob given here.
+    Glob(String),
+    /// Not recognized as an image, e.g. `Thumbs.db` or `ComicInfo.xml`.
+    NotAnImage,
+    /// A zip inside the archive, whose pages form a chapter.
+    NestedArchive,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::AppleDouble => write!(f, "AppleDouble resource fork"),
+            SkipReason::Hidden => write!(f, "hidden file"),
+            SkipReason::Empty => write!(f, "empty entry"),
+            SkipReason::Glob(pattern) => write!(f, "matches {}", pattern),
+            SkipReason::NotAnImage => write!(f, "not an image"),
+            SkipReason::NestedArchive => write!(f, "nested archive"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedEntry {
+    pub name: String,
+    pub reason: SkipReason,
+}
@@ -1,5 +1,43 @@
 pub mod archive;
+pub mod cb7;
+pub mod cbt;
+pub mod chapter;
+pub mod comic_info;
+pub mod directory;
+pub mod epub;
 pub mod error;
+pub mod filter;
+#[cfg(feature = "lzma")]
+mod lzma;
+pub mod mokuro;
+pub mod names;
+pub mod natural;
+pub mod ocr;
+pub mod recovery;
+pub mod repack;
+pub mod source;
+#[cfg(test)]
+mod test_util;
+#[cfg(feature = "transcode")]
+pub mod transcode;
+pub mod writer;
 
-pub use archive::{CbzArchive, ImageEntry, ImageFormat, ImageStream};
+pub use archive::{CbzArchive, EntryError, HealthReport, ImageEntry, ImageFormat, ImageStream};
+pub use cb7::Cb7Archive;
+pub use cbt::CbtArchive;
+pub use chapter::{Chapter, NESTED_SEPARATOR};
+pub use comic_info::{ComicInfo, ComicPage, Manga, PageType};
+pub use directory::ComicDirectory;
+pub use epub::EpubArchive;
 pub use error::{CbzError, Result};
+pub use filter::{FilterPolicy, SkipReason, SkippedEntry};
+pub use mokuro::{MokuroPage, MokuroVolume};
+pub use names::NameEncoding;
+pub use natural::natural_cmp;
+pub use ocr::{OcrBlock, OcrPage, OcrSidecar, embed_ocr_sidecar};
+pub use recovery::Recovery;
+pub use repack::{RepackOptions, RepackReport, repack};
+pub use source::{ComicSource, SourceKind, open_source};
+#[cfg(feature = "transcode")]
+pub use transcode::{Transcode, TranscodeFormat};
+pub use writer::CbzWriter;
@@ -0,0 +1,38 @@
+use crate::error::{CbzError, Result};
+use lzma_rs::decompress::{Options, UnpackedSize};
+use std::io::{self, Read};
+use zip::ZipArchive;
+
+/// Reads the LZMA entry at `index`, checking its CRC.
+///
+/// The zip crate hands entries to lzma-rs as if they were `.lzma` files, but
+/// the header of the ZIP format differs, so they are decoded here instead.
+pub(crate) fn read_lzma<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    index: usize,
+    name: &str,
+) -> Result<Vec<u8>> {
+    let mut entry = archive.by_index_raw(index)?;
+    let (size, crc) = (entry.size(), entry.crc32());
+    let mut compressed = Vec::with_capacity(entry.compressed_size() as usize);
+    entry.read_to_end(&mut compressed)?;
+
+    // A version and the length of the properties, two bytes each, come
+    // before the properties. The unpacked size is in the central directory
+    // rather than after them.
+    let mut stream = compressed
+        .get(4..)
+        .ok_or_else(|| CbzError::Truncated(name.to_string()))?;
+    let options = Options {
+        unpacked_size: UnpackedSize::UseProvided(Some(size)),
+        ..Options::default()
+    };
+    let mut data = Vec::with_capacity(size as usize);
+    lzma_rs::lzma_decompress_with_options(&mut stream, &mut data, &options)
+        .map_err(|_| CbzError::CrcMismatch(name.to_string()))?;
+
+    if data.len() as u64 != size || crc32fast::hash(&data) != crc {
+        return Err(CbzError::CrcMismatch(name.to_string()));
+    }
+    Ok(data)
+}
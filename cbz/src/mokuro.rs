@@ -0,0 +1,438 @@
+//! Conversion from and to the output of [Mokuro], so volumes it processed
+//! need not be OCR'd again, and yonde's results open in Mokuro readers.
+//!
+//! Mokuro writes one JSON file per page in `_ocr/<volume>/`, and since 0.2
+//! a `<volume>.mokuro` file holding all of them.
+//!
+//! [Mokuro]: https://github.com/kha-white/mokuro
+
+use crate::error::{CbzError, Result};
+use crate::names::safe_relative_path;
+use crate::ocr::{OcrBlock, OcrPage, OcrSidecar};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Start of the [`OcrSidecar::model_version`] of results imported from
+/// Mokuro, followed by its version.
+pub const MOKURO_MODEL_PREFIX: &str = "mokuro ";
+
+/// Format version written on export, that of Mokuro 0.2.
+pub const MOKURO_VERSION: &str = "0.2.1";
+
+/// One page, as in a per-page file or the `pages` of a `.mokuro` file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MokuroPage {
+    #[serde(default)]
+    pub version: String,
+    pub img_width: u32,
+    pub img_height: u32,
+    pub blocks: Vec<MokuroBlock>,
+    /// Path of the image in the volume, only in `.mokuro` files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub img_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MokuroBlock {
+    /// `[xmin, ymin, xmax, ymax]` in pixels of the page.
+    #[serde(rename = "box")]
+    pub bbox: [f32; 4],
+    #[serde(default)]
+    pub vertical: bool,
+    #[serde(default)]
+    pub font_size: f32,
+    /// Corners of each line, clockwise from the top left.
+    #[serde(default)]
+    pub lines_coords: Vec<[[f32; 2]; 4]>,
+    pub lines: Vec<String>,
+}
+
+/// A `.mokuro` file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MokuroVolume {
+    pub version: String,
+    pub title: String,
+    pub title_uuid: String,
+    pub volume: String,
+    pub volume_uuid: String,
+    pub pages: Vec<MokuroPage>,
+}
+
+impl MokuroPage {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        serde_json::from_slice(data).map_err(invalid)
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("page serializes")
+    }
+
+    pub fn to_ocr_page(&self) -> OcrPage {
+        let blocks = self
+            .blocks
+            .iter()
+            .map(|block| OcrBlock {
+                text: block.lines.concat(),
+                bbox: block.bbox.map(|v| v.round().max(0.0) as u32),
+                confidence: 0.0,
+                lines: block.lines.clone(),
+                vertical: Some(block.vertical),
+                font_size: Some(block.font_size),
+            })
+            .collect();
+        OcrPage {
+            width: self.img_width,
+            height: self.img_height,
+            blocks,
+        }
+    }
+
+    /// Converts `page`, guessing what Mokuro records and yonde may not know:
+    /// text in a block taller than wide is taken as vertical, and lines as
+    /// evenly splitting the block.
+    pub fn from_ocr_page(page: &OcrPage) -> Self {
+        let blocks = page
+            .blocks
+            .iter()
+            .map(|block| {
+                let [xmin, ymin, xmax, ymax] = block.bbox.map(|v| v as f32);
+                let lines = if block.lines.is_empty() {
+                    vec![block.text.clone()]
+                } else {
+                    block.lines.clone()
+                };
+                let vertical = block.vertical.unwrap_or(ymax - ymin > xmax - xmin);
+                // Vertical lines are columns, read right to left.
+                let count = lines.len() as f32;
+                let step = if vertical {
+                    (xmax - xmin) / count
+                } else {
+                    (ymax - ymin) / count
+                };
+                let lines_coords = (0..lines.len())
+                    .map(|i| {
+                        let i = i as f32;
+                        if vertical {
+                            let (right, left) = (xmax - i * step, xmax - (i + 1.0) * step);
+                            [[left, ymin], [right, ymin], [right, ymax], [left, ymax]]
+                        } else {
+                            let (top, bottom) = (ymin + i * step, ymin + (i + 1.0) * step);
+                            [[xmin, top], [xmax, top], [xmax, bottom], [xmin, bottom]]
+                        }
+                    })
+                    .collect();
+                MokuroBlock {
+                    bbox: [xmin, ymin, xmax, ymax],
+                    vertical,
+                    font_size: block.font_size.unwrap_or(step),
+                    lines_coords,
+                    lines,
+                }
+            })
+            .collect();
+        MokuroPage {
+            version: MOKURO_VERSION.to_string(),
+            img_width: page.width,
+            img_height: page.height,
+            blocks,
+            img_path: None,
+        }
+    }
+}
+
+impl MokuroVolume {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        serde_json::from_slice(data).map_err(invalid)
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("volume serializes")
+    }
+
+    /// Converts to a sidecar for the archive whose pages are `pages`.
+    ///
+    /// Each Mokuro page goes to the page with the same path, ignoring the
+    /// extension, or else the same file name. Pages without a match, or
+    /// without a size like those [`Self::from_sidecar`] pads with, are
+    /// dropped.
+    pub fn to_sidecar(&self, pages: &[String]) -> OcrSidecar {
+        let mut sidecar = OcrSidecar::new(format!("{}{}", MOKURO_MODEL_PREFIX, self.version));
+        let matcher = PageMatcher::new(pages);
+        for (index, page) in self.pages.iter().enumerate() {
+            let name = match &page.img_path {
+                Some(path) => matcher.find(path),
+                None => pages.get(index),
+            };
+            if page.img_width == 0 {
+                continue;
+            }
+            if let Some(name) = name {
+                sidecar.pages.insert(name.clone(), page.to_ocr_page());
+            }
+        }
+        sidecar
+    }
+
+    /// Converts `sidecar` for the archive whose pages are `pages`, in reading
+    /// order. Pages without results are kept, empty and without a size,
+    /// since readers match images by position.
+    pub fn from_sidecar(sidecar: &OcrSidecar, pages: &[String], title: &str, volume: &str) -> Self {
+        let pages = pages
+            .iter()
+            .map(|name| {
+                let mut page = match sidecar.pages.get(name) {
+                    Some(page) => MokuroPage::from_ocr_page(page),
+                    None => MokuroPage {
+                        version: MOKURO_VERSION.to_string(),
+                        ..MokuroPage::default()
+                    },
+                };
+                page.img_path = Some(name.clone());
+                page
+            })
+            .collect();
+        MokuroVolume {
+            version: MOKURO_VERSION.to_string(),
+            title: title.to_string(),
+            title_uuid: name_uuid(title),
+            volume: volume.to_string(),
+            volume_uuid: name_uuid(&format!("{}/{}", title, volume)),
+            pages,
+        }
+    }
+}
+
+/// Reads the per-page files of `dir`, an `_ocr/<volume>` folder, for the
+/// archive whose pages are `pages`. Pages without a file are left out.
+pub fn read_mokuro_pages(dir: &Path, pages: &[String]) -> Result<OcrSidecar> {
+    let mut version = None;
+    let mut sidecar = OcrSidecar::new("");
+    for name in pages {
+        let Some(path) = page_file(dir, name) else {
+            continue;
+        };
+        let page = MokuroPage::parse(&fs::read(path)?)?;
+        version.get_or_insert_with(|| page.version.clone());
+        sidecar.pages.insert(name.clone(), page.to_ocr_page());
+    }
+    sidecar.model_version = format!("{}{}", MOKURO_MODEL_PREFIX, version.unwrap_or_default());
+    Ok(sidecar)
+}
+
+/// Writes the pages of `sidecar` to `dir` as per-page files, named after the
+/// pages with a `.json` extension.
+///
+/// Fails with [`CbzError::UnsafeName`], before writing anything, when a page
+/// name would leave `dir`.
+pub fn write_mokuro_pages(sidecar: &OcrSidecar, dir: &Path) -> Result<()> {
+    let files = sidecar
+        .pages
+        .iter()
+        .map(|(name, page)| {
+            let path = safe_relative_path(&format!("{}.json", without_extension(name)))
+                .ok_or_else(|| CbzError::UnsafeName(name.clone()))?;
+            Ok((dir.join(path), page))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (path, page) in files {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, MokuroPage::from_ocr_page(page).to_json())?;
+    }
+    Ok(())
+}
+
+/// Whether `sidecar` holds results imported from Mokuro.
+pub fn is_from_mokuro(sidecar: &OcrSidecar) -> bool {
+    sidecar.model_version.starts_with(MOKURO_MODEL_PREFIX)
+}
+
+fn page_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    let stem = without_extension(name);
+    let file_name = stem.rsplit('/').next().unwrap_or(stem);
+    [stem, file_name]
+        .into_iter()
+        .filter_map(|stem| safe_relative_path(&format!("{}.json", stem)))
+        .map(|path| dir.join(path))
+        .find(|path| path.is_file())
+}
+
+/// Finds archive pages by path without extension, or by file name.
+struct PageMatcher<'a> {
+    by_path: HashMap<&'a str, &'a String>,
+    by_file_name: HashMap<&'a str, &'a String>,
+}
+
+impl<'a> PageMatcher<'a> {
+    fn new(pages: &'a [String]) -> Self {
+        let mut matcher = PageMatcher {
+            by_path: HashMap::new(),
+            by_file_name: HashMap::new(),
+        };
+        for page in pages {
+            let stem = without_extension(page);
+            matcher.by_path.entry(stem).or_insert(page);
+            let file_name = stem.rsplit('/').next().unwrap_or(stem);
+            matcher.by_file_name.entry(file_name).or_insert(page);
+        }
+        matcher
+    }
+
+    fn find(&self, path: &str) -> Option<&'a String> {
+        let path = path.replace('\\', "/");
+        let stem = without_extension(&path);
+        let file_name = stem.rsplit('/').next().unwrap_or(stem);
+        self.by_path
+            .get(stem)
+            .or_else(|| self.by_file_name.get(file_name))
+            .copied()
+    }
+}
+
+fn without_extension(name: &str) -> &str {
+    match name.rfind('.') {
+        Some(dot) if !name[dot..].contains('/') => &name[..dot],
+        _ => name,
+    }
+}
+
+/// A UUID-shaped id derived from `name`, so exporting twice gives the same
+/// ids and readers keep their progress.
+fn name_uuid(name: &str) -> String {
+    let part = |salt: &[u8]| {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(salt);
+        hasher.update(name.as_bytes());
+        hasher.finalize()
+    };
+    let (a, b, c, d) = (part(b"a"), part(b"b"), part(b"c"), part(b"d"));
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:04x}{:08x}",
+        a,
+        b >> 16,
+        b & 0xffff,
+        c >> 16,
+        c & 0xffff,
+        d
+    )
+}
+
+fn invalid(e: serde_json::Error) -> CbzError {
+    CbzError::InvalidMokuro(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"{
+        "version": "0.1.8",
+        "img_width": 1000,
+        "img_height": 1500,
+        "blocks": [{
+            "box": [100, 200, 160, 500],
+            "vertical": true,
+            "font_size": 28.0,
+            "lines_coords": [
+                [[130.5, 200.0], [160.0, 200.0], [160.0, 500.0], [130.5, 500.0]],
+                [[100.0, 200.0], [130.5, 200.0], [130.5, 400.0], [100.0, 400.0]]
+            ],
+            "lines": ["よんで", "ください"]
+        }]
+    }"#;
+
+    #[test]
+    fn test_page_round_trip() {
+        let page = MokuroPage::parse(PAGE.as_bytes()).unwrap();
+        let ocr = page.to_ocr_page();
+        assert_eq!((ocr.width, ocr.height), (1000, 1500));
+        let block = &ocr.blocks[0];
+        assert_eq!(block.text, "よんでください");
+        assert_eq!(block.bbox, [100, 200, 160, 500]);
+        assert_eq!(block.vertical, Some(true));
+
+        let exported = MokuroPage::from_ocr_page(&ocr);
+        assert_eq!(exported.blocks[0].lines, page.blocks[0].lines);
+        assert_eq!(exported.blocks[0].font_size, 28.0);
+        assert_eq!(exported.blocks[0].lines_coords.len(), 2);
+        assert_eq!(exported.to_ocr_page(), ocr);
+    }
+
+    #[test]
+    fn test_export_guesses() {
+        let page = OcrPage {
+            width: 100,
+            height: 100,
+            blocks: vec![OcrBlock {
+                text: "横".to_string(),
+                bbox: [0, 0, 80, 20],
+                ..OcrBlock::default()
+            }],
+        };
+        let block = &MokuroPage::from_ocr_page(&page).blocks[0];
+        assert!(!block.vertical);
+        assert_eq!(block.font_size, 20.0);
+        assert_eq!(block.lines, ["横"]);
+    }
+
+    #[test]
+    fn test_volume() {
+        let pages = ["vol/001.jpg".to_string(), "vol/002.jpg".to_string()];
+        let mut volume = MokuroVolume {
+            version: "0.2.1".to_string(),
+            pages: vec![MokuroPage::parse(PAGE.as_bytes()).unwrap()],
+            ..MokuroVolume::default()
+        };
+        volume.pages[0].img_path = Some("002.png".to_string());
+
+        let sidecar = volume.to_sidecar(&pages);
+        assert_eq!(sidecar.model_version, "mokuro 0.2.1");
+        assert!(is_from_mokuro(&sidecar));
+        assert_eq!(sidecar.pages.keys().collect::<Vec<_>>(), ["vol/002.jpg"]);
+
+        let exported = MokuroVolume::from_sidecar(&sidecar, &pages, "Title", "Volume 1");
+        assert_eq!(exported.pages.len(), 2);
+        assert!(exported.pages[0].blocks.is_empty());
+        assert_eq!(exported.pages[1].img_path.as_deref(), Some("vol/002.jpg"));
+        assert_eq!(exported.to_sidecar(&pages).pages, sidecar.pages);
+        assert_ne!(exported.title_uuid, exported.volume_uuid);
+    }
+
+    #[test]
+    fn test_page_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let pages = ["a/001.jpg".to_string(), "a/002.jpg".to_string()];
+        let mut sidecar = OcrSidecar::new("comic-ocr 0.1.0");
+        let page = MokuroPage::parse(PAGE.as_bytes()).unwrap().to_ocr_page();
+        sidecar.pages.insert(pages[0].clone(), page);
+
+        write_mokuro_pages(&sidecar, dir.path()).unwrap();
+        assert!(dir.path().join("a/001.json").is_file());
+        let read = read_mokuro_pages(dir.path(), &pages).unwrap();
+        assert_eq!(read.model_version, "mokuro 0.2.1");
+        assert_eq!(read.pages, sidecar.pages);
+    }
+
+    #[test]
+    fn test_page_files_stay_in_folder() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("out");
+        let mut sidecar = OcrSidecar::new("comic-ocr 0.1.0");
+        let page = MokuroPage::parse(PAGE.as_bytes()).unwrap().to_ocr_page();
+        sidecar.pages.insert("001.jpg".to_string(), page.clone());
+        sidecar.pages.insert("../evil.jpg".to_string(), page);
+
+        let err = write_mokuro_pages(&sidecar, &dir).unwrap_err();
+        assert!(matches!(err, CbzError::UnsafeName(name) if name == "../evil.jpg"));
+        assert!(!root.path().join("evil.json").exists());
+        assert!(!dir.exists());
+
+        fs::write(root.path().join("evil.json"), PAGE).unwrap();
+        fs::create_dir(&dir).unwrap();
+        let read = read_mokuro_pages(&dir, &["../evil.jpg".to_string()]).unwrap();
+        assert!(read.pages.is_empty());
+    }
+}
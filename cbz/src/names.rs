@@ -0,0 +1,113 @@
+use encoding_rs::SHIFT_JIS;
+use std::path::{Component, Path, PathBuf};
+
+/// Character set of entry names that lack the zip UTF-8 flag.
+///
+/// Japanese archivers commonly write names in CP932 (Shift-JIS) without
+/// setting the flag, which the zip format would otherwise read as CP437.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameEncoding {
+    /// Pick UTF-8, then Shift-JIS, then CP437, whichever decodes every name.
+    #[default]
+    Auto,
+    Utf8,
+    ShiftJis,
+    Cp437,
+}
+
+/// Decodes the raw entry names of an archive.
+///
+/// `cp437` holds the names as the zip crate decoded them, used for
+/// [`NameEncoding::Cp437`]. Returns the encoding actually used, which is never
+/// `Auto`.
+pub(crate) fn decode_names(
+    raw: &[Vec<u8>],
+    cp437: &[String],
+    encoding: NameEncoding,
+) -> (NameEncoding, Vec<String>) {
+    let encoding = match encoding {
+        NameEncoding::Auto => detect(raw),
+        other => other,
+    };
+
+    let names = raw
+        .iter()
+        .zip(cp437)
+        .map(|(raw, cp437)| match encoding {
+            NameEncoding::Utf8 | NameEncoding::Auto => String::from_utf8_lossy(raw).into_owned(),
+            // Names written with the UTF-8 flag are valid UTF-8 already.
+            NameEncoding::ShiftJis => match std::str::from_utf8(raw) {
+                Ok(name) => name.to_string(),
+                Err(_) => SHIFT_JIS.decode_without_bom_handling(raw).0.into_owned(),
+            },
+            NameEncoding::Cp437 => cp437.clone(),
+        })
+        .collect();
+    (encoding, names)
+}
+
+/// Path of the entry `name` below a folder it is extracted or exported to.
+///
+/// Entry names are untrusted: `None` for one that would leave the folder,
+/// such as `../x` or `/x`, or that names no file. Backslashes count as
+/// separators, as some Windows archivers write them.
+pub fn safe_relative_path(name: &str) -> Option<PathBuf> {
+    let name = name.replace('\\', "/");
+    let mut path = PathBuf::new();
+    for component in Path::new(&name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!path.as_os_str().is_empty()).then_some(path)
+}
+
+fn detect(raw: &[Vec<u8>]) -> NameEncoding {
+    let not_utf8: Vec<&Vec<u8>> = raw
+        .iter()
+        .filter(|name| std::str::from_utf8(name).is_err())
+        .collect();
+    if not_utf8.is_empty() {
+        return NameEncoding::Utf8;
+    }
+
+    let shift_jis = not_utf8.iter().all(|name| {
+        SHIFT_JIS
+            .decode_without_bom_handling_and_without_replacement(name)
+            .is_some()
+    });
+    if shift_jis {
+        NameEncoding::ShiftJis
+    } else {
+        NameEncoding::Cp437
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_relative_path() {
+        assert_eq!(
+            safe_relative_path("vol/./001.jpg"),
+            Some(PathBuf::from("vol/001.jpg"))
+        );
+        assert_eq!(
+            safe_relative_path("vol\\001.jpg"),
+            Some(PathBuf::from("vol/001.jpg"))
+        );
+        for name in [
+            "../evil.jpg",
+            "vol/../../evil.jpg",
+            "/etc/evil",
+            "\\evil",
+            ".",
+            "",
+        ] {
+            assert_eq!(safe_relative_path(name), None, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use icu_collator::{Collator, CollatorOptions, Strength};
+use icu_normalizer::ComposingNormalizer;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+thread_local! {
+    // Japanese collation, which orders Latin text as English does too.
+    static COLLATOR: Collator = {
+        let mut options = CollatorOptions::new();
+        // Ignores case, width and hiragana versus katakana, but not accents
+        // or voicing marks.
+        options.strength = Some(Strength::Secondary);
+        Collator::try_new(&"ja".parse().unwrap(), options).unwrap()
+    };
+}
+
+/// Compares two entry paths the way a person would order pages.
+///
+/// Paths are compared one component at a time, so everything inside `ch2/`
+/// sorts before `ch10/`. Within a component, runs of digits compare by value
+/// (`page2` < `page10`), full-width digits included, and the text between
+/// them by Japanese collation: case, full-width and half-width forms, and
+/// hiragana versus katakana are ignored, and kanji follow JIS order rather
+/// than code points. Paths only differing in those compare by code point.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split(['/', '\\']);
+    let mut b_parts = b.split(['/', '\\']);
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match compare_component(x, y) {
+                Ordering::Equal => continue,
+                other => return other,
+            },
+        }
+    }
+}
+
+fn compare_component(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        let (x, y) = match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => (fold(x), fold(y)),
+        };
+
+        let order = if x.is_ascii_digit() && y.is_ascii_digit() {
+            compare_numbers(&take_digits(&mut a), &take_digits(&mut b))
+        } else {
+            // Half-width kana only match full-width ones once their separate
+            // voicing marks are composed.
+            let nfkc = ComposingNormalizer::new_nfkc();
+            let (x, y) = (
+                nfkc.normalize(&take_text(&mut a)),
+                nfkc.normalize(&take_text(&mut b)),
+            );
+            COLLATOR.with(|collator| collator.compare(&x, &y))
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+/// Takes the characters up to the next digit.
+fn take_text(chars: &mut Peekable<Chars<'_>>) -> String {
+    let mut text = String::new();
+    while let Some(&c) = chars.peek() {
+        if fold(c).is_ascii_digit() {
+            break;
+        }
+        text.push(c);
+        chars.next();
+    }
+    text
+}
+
+fn take_digits(chars: &mut Peekable<Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().map(|&c| fold(c)) {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(c);
+        chars.next();
+    }
+    digits
+}
+
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let a_value = a.trim_start_matches('0');
+    let b_value = b.trim_start_matches('0');
+    a_value
+        .len()
+        .cmp(&b_value.len())
+        .then_with(|| a_value.cmp(b_value))
+        // `01` and `1` have the same value, keep the padded one first.
+        .then_with(|| b.len().cmp(&a.len()))
+}
+
+/// Maps full-width ASCII to ASCII.
+fn fold(c: char) -> char {
+    match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|s| s.to_string()).collect();
+        names.sort_by(|a, b| natural_cmp(a, b));
+        names
+    }
+
+    #[test]
+    fn test_numeric_runs() {
+        assert_eq!(
+            sorted(&["page10.jpg", "page2.jpg", "page1.jpg"]),
+            ["page1.jpg", "page2.jpg", "page10.jpg"]
+        );
+    }
+
+    #[test]
+    fn test_components() {
+        assert_eq!(
+            sorted(&["ch10/001.jpg", "ch2/010.jpg", "ch2/002.jpg"]),
+            ["ch2/002.jpg", "ch2/010.jpg", "ch10/001.jpg"]
+        );
+    }
+
+    #[test]
+    fn test_case_and_width() {
+        assert_eq!(
+            sorted(&["Page3.png", "page１２.png", "PAGE２.png"]),
+            ["PAGE２.png", "Page3.png", "page１２.png"]
+        );
+    }
+
+    #[test]
+    fn test_kana() {
+        // Hiragana and katakana, and half-width katakana, sort together.
+        assert_eq!(
+            sorted(&["か.jpg", "ア.jpg", "あ.jpg"]),
+            ["あ.jpg", "ア.jpg", "か.jpg"]
+        );
+        assert_eq!(
+            sorted(&["ﾍﾟｰｼﾞ2.jpg", "ぺーじ3.jpg", "ページ1.jpg"]),
+            ["ページ1.jpg", "ﾍﾟｰｼﾞ2.jpg", "ぺーじ3.jpg"]
+        );
+        // Voicing marks still count.
+        assert_eq!(sorted(&["ば.jpg", "は.jpg"]), ["は.jpg", "ば.jpg"]);
+    }
+
+    #[test]
+    fn test_kanji() {
+        // JIS order, by reading for common kanji: 亜 (あ) before 一 (いち),
+        // though its code point is higher.
+        assert_eq!(sorted(&["一.jpg", "亜.jpg"]), ["亜.jpg", "一.jpg"]);
+        assert_eq!(
+            sorted(&["第10話/01.jpg", "第2話/01.jpg"]),
+            ["第2話/01.jpg", "第10話/01.jpg"]
+        );
+    }
+
+    #[test]
+    fn test_leading_zeros() {
+        assert_eq!(
+            sorted(&["1.jpg", "01.jpg", "002.jpg"]),
+            ["01.jpg", "1.jpg", "002.jpg"]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use crate::error::{CbzError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// Entry holding the OCR results of the pages, so a volume OCR'd once can be
+/// shared without running the models again.
+pub const OCR_SIDECAR_NAME: &str = "yonde/ocr.json";
+
+/// Version of the sidecar format written by this crate. Newer ones are
+/// rejected, older ones are read as they are.
+pub const OCR_SIDECAR_VERSION: u32 = 1;
+
+/// Contents of [`OCR_SIDECAR_NAME`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OcrSidecar {
+    pub version: u32,
+    /// Identifies the models that produced the results, which are only
+    /// reused by the same models.
+    pub model_version: String,
+    /// Results by page name.
+    pub pages: BTreeMap<String, OcrPage>,
+}
+
+/// The text found on one page.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OcrPage {
+    pub width: u32,
+    pub height: u32,
+    pub blocks: Vec<OcrBlock>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OcrBlock {
+    pub text: String,
+    /// `[xmin, ymin, xmax, ymax]` in pixels of the page.
+    pub bbox: [u32; 4],
+    /// Detection confidence, 0 when unknown, as for imported results.
+    #[serde(default)]
+    pub confidence: f32,
+    /// Text of each line, when known, which `text` joins.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<String>,
+    /// Whether the text runs top to bottom, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vertical: Option<bool>,
+    /// Font size in pixels, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f32>,
+}
+
+impl OcrSidecar {
+    pub fn new(model_version: impl Into<String>) -> Self {
+        Self {
+            version: OCR_SIDECAR_VERSION,
+            model_version: model_version.into(),
+            pages: BTreeMap::new(),
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        // Checked first, as a newer version may not deserialize at all.
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+        let header: Header = serde_json::from_slice(data).map_err(invalid)?;
+        if header.version > OCR_SIDECAR_VERSION {
+            return Err(CbzError::InvalidOcrSidecar(format!(
+                "unsupported version {}",
+                header.version
+            )));
+        }
+        serde_json::from_slice(data).map_err(invalid)
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).expect("sidecar serializes")
+    }
+}
+
+/// Stores `sidecar` in the zip archive at `path`, replacing the one it may
+/// already hold.
+///
+/// The other entries are copied without being decompressed into a new file,
+/// which then takes the place of the archive.
+pub fn embed_ocr_sidecar(path: impl AsRef<Path>, sidecar: &OcrSidecar) -> Result<()> {
+    let path = path.as_ref();
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".tmp");
+    let temp = path.with_file_name(file_name);
+
+    let written = (|| {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let mut writer = ZipWriter::new(BufWriter::new(File::create(&temp)?));
+        for index in 0..archive.len() {
+            let entry = archive.by_index_raw(index)?;
+            if entry.name() != OCR_SIDECAR_NAME {
+                writer.raw_copy_file(entry)?;
+            }
+        }
+        writer.start_file(OCR_SIDECAR_NAME, SimpleFileOptions::default())?;
+        writer.write_all(&sidecar.to_json())?;
+        writer.finish()?.flush()?;
+        Ok(())
+    })();
+    match written {
+        Ok(()) => Ok(fs::rename(&temp, path)?),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+fn invalid(e: serde_json::Error) -> CbzError {
+    CbzError::InvalidOcrSidecar(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CbzArchive;
+
+    fn sample() -> OcrSidecar {
+        let mut sidecar = OcrSidecar::new("comic-ocr 0.1.0");
+        sidecar.pages.insert(
+            "001.jpg".to_string(),
+            OcrPage {
+                width: 800,
+                height: 1200,
+                blocks: vec![OcrBlock {
+                    text: "読んで".to_string(),
+                    bbox: [10, 20, 110, 220],
+                    confidence: 0.9,
+                    ..OcrBlock::default()
+                }],
+            },
+        );
+        sidecar
+    }
+
+    #[test]
+    fn test_parse() {
+        let sidecar = sample();
+        assert_eq!(OcrSidecar::parse(&sidecar.to_json()).unwrap(), sidecar);
+
+        let newer = br#"{"version": 2, "pages": []}"#;
+        assert!(matches!(
+            OcrSidecar::parse(newer),
+            Err(CbzError::InvalidOcrSidecar(_))
+        ));
+    }
+
+    #[test]
+    fn test_embed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        let mut writer = crate::CbzWriter::create(&path, 1).unwrap();
+        writer.add_page(&[0xFF, 0xD8, 0xFF, 0xE0]).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(
+            CbzArchive::open(&path).unwrap().ocr_sidecar().unwrap(),
+            None
+        );
+
+        let mut sidecar = sample();
+        embed_ocr_sidecar(&path, &sidecar).unwrap();
+        sidecar.model_version = "comic-ocr 0.2.0".to_string();
+        embed_ocr_sidecar(&path, &sidecar).unwrap();
+
+        let mut archive = CbzArchive::open(&path).unwrap();
+        assert_eq!(archive.file_names(), ["001.jpg", OCR_SIDECAR_NAME]);
+        assert_eq!(archive.pages(), ["001.jpg"]);
+        assert_eq!(archive.ocr_sidecar().unwrap(), Some(sidecar));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::archive::{CbzArchive, EntryError};
+use crate::error::{CbzError, Result};
+use crate::names::{NameEncoding, decode_names};
+use flate2::read::DeflateDecoder;
+use std::collections::HashSet;
+use std::io::{Cursor, Read, Write};
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+const LOCAL_HEADER: &[u8] = b"PK\x03\x04";
+const DATA_DESCRIPTOR: &[u8] = b"PK\x07\x08";
+const LOCAL_HEADER_LEN: usize = 30;
+
+/// What [`CbzArchive::recover`] could save from a damaged archive.
+pub struct Recovery {
+    /// A new archive holding every entry that read back intact.
+    pub archive: CbzArchive<Cursor<Vec<u8>>>,
+    pub lost: Vec<EntryError>,
+}
+
+impl CbzArchive<Cursor<Vec<u8>>> {
+    /// Salvages the entries of an archive whose central directory is missing
+    /// or broken, such as a partial download.
+    ///
+    /// Local file headers are scanned in order and each entry is checked
+    /// against its CRC. Entries that are cut off, corrupt, encrypted, or use
+    /// a method other than stored or deflate are reported in
+    /// [`Recovery::lost`], along with later copies of an entry already saved.
+    pub fn recover(data: &[u8]) -> Result<Recovery> {
+        let mut found = Vec::new();
+        let mut offset = 0;
+        while let Some(start) = find(data, LOCAL_HEADER, offset) {
+            match LocalEntry::parse(data, start) {
+                Some(entry) => {
+                    offset = entry.end.unwrap_or(start + LOCAL_HEADER_LEN);
+                    found.push(entry);
+                }
+                None => offset = start + LOCAL_HEADER.len(),
+            }
+        }
+
+        let raw: Vec<Vec<u8>> = found.iter().map(|entry| entry.name.clone()).collect();
+        let lossy: Vec<String> = raw
+            .iter()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+        let (_, names) = decode_names(&raw, &lossy, NameEncoding::Auto);
+
+        let mut buffer = Vec::new();
+        let mut writer = ZipWriter::new(Cursor::new(&mut buffer));
+        let mut lost = Vec::new();
+        let mut saved = HashSet::new();
+        for (entry, name) in found.iter().zip(names) {
+            if name.ends_with('/') {
+                continue;
+            }
+            if saved.contains(&name) {
+                let error = CbzError::DuplicateEntry(name.clone());
+                lost.push(EntryError { name, error });
+                continue;
+            }
+            match entry.contents(data, &name) {
+                Ok(contents) => {
+                    // Pages are compressed images already.
+                    let options = SimpleFileOptions::default()
+                        .compression_method(zip::CompressionMethod::Stored);
+                    writer.start_file(name.as_str(), options)?;
+                    writer.write_all(&contents)?;
+                    saved.insert(name);
+                }
+                Err(error) => lost.push(EntryError { name, error }),
+            }
+        }
+        writer.finish()?;
+
+        Ok(Recovery {
+            archive: CbzArchive::from_bytes(buffer)?,
+            lost,
+        })
+    }
+}
+
+struct LocalEntry {
+    name: Vec<u8>,
+    // General purpose bit 0, with ZipCrypto or AES.
+    encrypted: bool,
+    method: u16,
+    crc: u32,
+    // Start of the compressed data.
+    data_start: usize,
+    // Compressed size, `None` when neither the header nor a data descriptor
+    // could tell it.
+    size: Option<usize>,
+    // Where the next entry can start, past the data and its descriptor.
+    end: Option<usize>,
+}
+
+impl LocalEntry {
+    fn parse(data: &[u8], start: usize) -> Option<Self> {
+        let header = data.get(start..start + LOCAL_HEADER_LEN)?;
+        let flags = u16_at(header, 6);
+        let method = u16_at(header, 8);
+        let mut crc = u32_at(header, 14);
+        let mut size = u32_at(header, 18) as usize;
+        let name_len = u16_at(header, 26) as usize;
+        let extra_len = u16_at(header, 28) as usize;
+
+        let name_start = start + LOCAL_HEADER_LEN;
+        let name = data.get(name_start..name_start + name_len)?.to_vec();
+        let data_start = name_start + name_len + extra_len;
+        let encrypted = flags & 0x01 != 0;
+
+        // Bit 3: sizes and CRC follow the data, in a data descriptor.
+        if flags & 0x08 == 0 {
+            let end = data_start + size;
+            return Some(Self {
+                name,
+                encrypted,
+                method,
+                crc,
+                data_start,
+                size: Some(size),
+                end: (end <= data.len()).then_some(end),
+            });
+        }
+
+        let mut search = data_start;
+        while let Some(descriptor) = find(data, DATA_DESCRIPTOR, search) {
+            size = descriptor - data_start;
+            if data.len() >= descriptor + 16 && u32_at(data, descriptor + 8) as usize == size {
+                crc = u32_at(data, descriptor + 4);
+                return Some(Self {
+                    name,
+                    encrypted,
+                    method,
+                    crc,
+                    data_start,
+                    size: Some(size),
+                    end: Some(descriptor + 16),
+                });
+            }
+            search = descriptor + DATA_DESCRIPTOR.len();
+        }
+        Some(Self {
+            name,
+            encrypted,
+            method,
+            crc,
+            data_start,
+            size: None,
+            end: None,
+        })
+    }
+
+    fn contents(&self, data: &[u8], name: &str) -> Result<Vec<u8>> {
+        let compressed = self
+            .size
+            .and_then(|size| data.get(self.data_start..self.data_start + size))
+            .ok_or_else(|| CbzError::Truncated(name.to_string()))?;
+        // The data would not match its CRC before being decrypted.
+        if self.encrypted {
+            return Err(CbzError::Encrypted(name.to_string()));
+        }
+
+        let contents = match self.method {
+            0 => compressed.to_vec(),
+            8 => {
+                let mut contents = Vec::new();
+                DeflateDecoder::new(compressed)
+                    .read_to_end(&mut contents)
+                    .map_err(|_| CbzError::CrcMismatch(name.to_string()))?;
+                contents
+            }
+            method => {
+                return Err(CbzError::UnsupportedCompression {
+                    name: name.to_string(),
+                    method: format!("method {}", method),
+                });
+            }
+        };
+
+        if crc32fast::hash(&contents) != self.crc {
+            return Err(CbzError::CrcMismatch(name.to_string()));
+        }
+        Ok(contents)
+    }
+}
+
+fn find(data: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| from + position)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{create_cbz_with, page};
+    use zip::CompressionMethod;
+    use zip::unstable::write::FileOptionsExt;
+
+    fn create_cbz(method: CompressionMethod) -> Vec<u8> {
+        let pages = [page(3), page(4), page(5)];
+        let entries = [
+            ("001.jpg", &pages[0][..]),
+            ("002.jpg", &pages[1][..]),
+            ("003.jpg", &pages[2][..]),
+        ];
+        create_cbz_with(&entries, method)
+    }
+
+    #[test]
+    fn test_recover_truncated() {
+        for method in [CompressionMethod::Stored, CompressionMethod::Deflated] {
+            let data = create_cbz(method);
+            let third = find(&data, LOCAL_HEADER, 0)
+                .and_then(|first| find(&data, LOCAL_HEADER, first + 1))
+                .and_then(|second| find(&data, LOCAL_HEADER, second + 1))
+                .unwrap();
+            // Cut in the middle of the third entry's data.
+            let truncated = &data[..third + 60];
+            let error = CbzArchive::from_bytes(truncated.to_vec()).err().unwrap();
+            assert!(error.is_damaged_archive());
+
+            let mut recovery = CbzArchive::recover(truncated).unwrap();
+            assert_eq!(recovery.archive.pages(), ["001.jpg", "002.jpg"]);
+            assert_eq!(recovery.archive.read_entry("002.jpg").unwrap(), page(4));
+            assert_eq!(recovery.lost.len(), 1);
+            assert_eq!(recovery.lost[0].name, "003.jpg");
+            assert!(matches!(recovery.lost[0].error, CbzError::Truncated(_)));
+        }
+    }
+
+    #[test]
+    fn test_recover_corrupt_entry() {
+        let mut data = create_cbz(CompressionMethod::Stored);
+        let first = find(&data, LOCAL_HEADER, 0).unwrap();
+        data[first + LOCAL_HEADER_LEN + "001.jpg".len() + 10] ^= 0xFF;
+
+        let mut recovery = CbzArchive::recover(&data).unwrap();
+        assert_eq!(recovery.archive.pages(), ["002.jpg", "003.jpg"]);
+        assert!(matches!(recovery.lost[0].error, CbzError::CrcMismatch(_)));
+    }
+
+    #[test]
+    fn test_recover_duplicate_entry() {
+        let data = create_cbz(CompressionMethod::Stored);
+        let second = find(&data, LOCAL_HEADER, 1).unwrap();
+        let other = create_cbz_with(&[("001.jpg", &page(9)[..])], CompressionMethod::Stored);
+        let central = find(&other, b"PK\x01\x02", 0).unwrap();
+        // Another 001.jpg after the first two entries, with no central
+        // directory.
+        let third = find(&data, LOCAL_HEADER, second + 1).unwrap();
+        let mut damaged = data[..third].to_vec();
+        damaged.extend_from_slice(&other[..central]);
+        assert!(CbzArchive::from_bytes(damaged.clone()).is_err());
+
+        let mut recovery = CbzArchive::recover(&damaged).unwrap();
+        assert_eq!(recovery.archive.pages(), ["001.jpg", "002.jpg"]);
+        assert_eq!(recovery.archive.read_entry("001.jpg").unwrap(), page(3));
+        assert_eq!(recovery.lost.len(), 1);
+        assert_eq!(recovery.lost[0].name, "001.jpg");
+        assert!(matches!(
+            recovery.lost[0].error,
+            CbzError::DuplicateEntry(_)
+        ));
+    }
+
+    #[test]
+    fn test_recover_encrypted_entry() {
+        // ZipCrypto keeps the method, so only the flag tells the entry apart
+        // from a corrupt one.
+        let mut buffer = Vec::new();
+        let mut writer = ZipWriter::new(Cursor::new(&mut buffer));
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .with_deprecated_encryption(b"secret");
+        writer.start_file("001.jpg", options).unwrap();
+        writer.write_all(&page(3)).unwrap();
+        writer
+            .start_file("002.jpg", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(&page(4)).unwrap();
+        writer.finish().unwrap();
+
+        let mut recovery = CbzArchive::recover(&buffer).unwrap();
+        assert_eq!(recovery.archive.pages(), ["002.jpg"]);
+        assert_eq!(recovery.lost.len(), 1);
+        assert_eq!(recovery.lost[0].name, "001.jpg");
+        assert!(matches!(recovery.lost[0].error, CbzError::Encrypted(_)));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::comic_info::{ComicInfo, ComicPage};
+use crate::error::Result;
+use crate::natural::natural_cmp;
+use crate::source::ComicSource;
+#[cfg(feature = "transcode")]
+use crate::transcode::Transcode;
+use crate::writer::CbzWriter;
+use std::io::{Seek, Write};
+
+/// How [`repack`] rewrites a comic.
+#[derive(Debug, Clone, Default)]
+pub struct RepackOptions {
+    /// Re-encodes the pages, which are copied as they are otherwise.
+    #[cfg(feature = "transcode")]
+    pub transcode: Option<Transcode>,
+    /// Written as `ComicInfo.xml` instead of the source's own metadata.
+    ///
+    /// Like the source's, its `pages` index the source images in natural
+    /// order, and are renumbered to match the repacked pages.
+    pub comic_info: Option<ComicInfo>,
+}
+
+/// What [`repack`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepackReport {
+    pub pages: usize,
+    /// Pages re-encoded, the others were copied.
+    pub transcoded: usize,
+    /// Size of the pages read.
+    pub bytes_in: u64,
+    /// Size of the pages written.
+    pub bytes_out: u64,
+}
+
+/// Rewrites `source` as a normalized CBZ into `writer`.
+///
+/// Only the pages are kept, in reading order and renamed by position with
+/// [`CbzWriter`], so junk the source's [`FilterPolicy`] skips, such as
+/// AppleDouble forks and `Thumbs.db`, is left behind. The metadata is copied
+/// with its page indices updated, unless [`RepackOptions::comic_info`]
+/// replaces it, and so are the OCR results, under the new page names.
+///
+/// [`FilterPolicy`]: crate::FilterPolicy
+pub fn repack<W: Write + Seek>(
+    source: &mut dyn ComicSource,
+    writer: W,
+    options: &RepackOptions,
+) -> Result<RepackReport> {
+    let pages = source.pages();
+    let sidecar = source.ocr_sidecar().ok().flatten();
+    let info = match &options.comic_info {
+        Some(info) => Some(info.clone()),
+        // A broken ComicInfo.xml is dropped rather than copied.
+        None => source.metadata().ok().flatten(),
+    };
+
+    let mut out = CbzWriter::new(writer, pages.len());
+    let mut report = RepackReport::default();
+    for name in &pages {
+        let page = source.read_page(name)?;
+        report.bytes_in += page.data.len() as u64;
+        #[cfg(feature = "transcode")]
+        let page = match &options.transcode {
+            Some(transcode) => match transcode.apply(&page)? {
+                Some(data) => {
+                    report.transcoded += 1;
+                    crate::ImageEntry { data, ..page }
+                }
+                None => page,
+            },
+            None => page,
+        };
+        report.bytes_out += page.data.len() as u64;
+        out.add_page(&page.data)?;
+        report.pages += 1;
+    }
+
+    if let Some(mut info) = info {
+        info.pages = renumber(&info.pages, &pages);
+        out.set_comic_info(info);
+    }
+    if let Some(mut sidecar) = sidecar {
+        let names = pages.iter().zip(out.pages());
+        sidecar.pages = names
+            .filter_map(|(old, new)| Some((new.clone(), sidecar.pages.remove(old)?)))
+            .collect();
+        out.set_ocr_sidecar(sidecar);
+    }
+    out.finish()?;
+    Ok(report)
+}
+
+/// Points `pages`, which index the images of `order` sorted naturally, at
+/// their position in `order`.
+fn renumber(pages: &[ComicPage], order: &[String]) -> Vec<ComicPage> {
+    let mut sorted: Vec<&String> = order.iter().collect();
+    sorted.sort_by(|a, b| natural_cmp(a, b));
+    pages
+        .iter()
+        .filter_map(|page| {
+            let name = sorted.get(page.image)?;
+            let image = order.iter().position(|other| other == *name)?;
+            Some(ComicPage {
+                image,
+                ..page.clone()
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CbzArchive;
+    use crate::comic_info::PageType;
+    use crate::test_util::{JPEG, create_cbz};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_repack() {
+        // The metadata moves page10.jpg, the last image in natural order, to
+        // the front.
+        let info = r#"<ComicInfo><Title>Old</Title><Pages>
+            <Page Image="2" Type="FrontCover" />
+            <Page Image="1" Type="BackCover" />
+        </Pages></ComicInfo>"#;
+        let sidecar = r#"{"version": 1, "model_version": "m", "pages": {
+            "page2.jpg": {"width": 1, "height": 1, "blocks": []}
+        }}"#;
+        let data = create_cbz(&[
+            ("page10.jpg", &[0xFF, 0xD8, 0xFF, 0xE1]),
+            ("page2.jpg", JPEG),
+            ("cover.jpg", &[0xFF, 0xD8, 0xFF, 0xE2]),
+            ("__MACOSX/._page2.jpg", JPEG),
+            ("Thumbs.db", b"junk"),
+            ("ComicInfo.xml", info.as_bytes()),
+            ("yonde/ocr.json", sidecar.as_bytes()),
+        ]);
+        let mut source = CbzArchive::from_bytes(data).unwrap();
+
+        let mut buffer = Vec::new();
+        let report = repack(
+            &mut source,
+            Cursor::new(&mut buffer),
+            &RepackOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(report.pages, 3);
+        assert_eq!(report.bytes_in, report.bytes_out);
+
+        let mut repacked = CbzArchive::from_bytes(buffer).unwrap();
+        assert_eq!(
+            repacked.file_names(),
+            [
+                "001.jpg",
+                "002.jpg",
+                "003.jpg",
+                "ComicInfo.xml",
+                "yonde/ocr.json"
+            ]
+        );
+        assert_eq!(repacked.pages(), ["001.jpg", "002.jpg", "003.jpg"]);
+        assert_eq!(
+            repacked.read_entry("001.jpg").unwrap(),
+            [0xFF, 0xD8, 0xFF, 0xE1]
+        );
+        assert_eq!(repacked.read_entry("002.jpg").unwrap(), JPEG);
+        assert_eq!(
+            repacked.read_entry("003.jpg").unwrap(),
+            [0xFF, 0xD8, 0xFF, 0xE2]
+        );
+        let info = repacked.metadata().unwrap().unwrap();
+        assert_eq!(info.title.as_deref(), Some("Old"));
+        let types: Vec<_> = info.pages.iter().map(|p| (p.image, p.page_type)).collect();
+        assert_eq!(types, [(0, PageType::FrontCover), (1, PageType::BackCover)]);
+        let sidecar = repacked.ocr_sidecar().unwrap().unwrap();
+        assert_eq!(sidecar.pages.keys().collect::<Vec<_>>(), ["002.jpg"]);
+    }
+
+    #[test]
+    // Without transcoding, `comic_info` is the only field.
+    #[cfg_attr(not(feature = "transcode"), allow(clippy::needless_update))]
+    fn test_repack_inject_metadata() {
+        let mut source = CbzArchive::from_bytes(create_cbz(&[("a.jpg", JPEG)])).unwrap();
+        let options = RepackOptions {
+            comic_info: Some(ComicInfo {
+                series: Some("New".to_string()),
+                ..ComicInfo::default()
+            }),
+            ..RepackOptions::default()
+        };
+        let mut buffer = Vec::new();
+        repack(&mut source, Cursor::new(&mut buffer), &options).unwrap();
+        let info = CbzArchive::from_bytes(buffer)
+            .unwrap()
+            .metadata()
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.series.as_deref(), Some("New"));
+    }
+}
@@ -0,0 +1,197 @@
+use crate::archive::{
+    CbzArchive, ImageEntry, ImageFormat, detect_image_format, has_image_extension,
+};
+use crate::cb7::Cb7Archive;
+use crate::cbt::CbtArchive;
+use crate::chapter::{Chapter, group_by_directory};
+use crate::comic_info::{COMIC_INFO_NAME, ComicInfo};
+use crate::directory::ComicDirectory;
+use crate::epub::EpubArchive;
+use crate::error::{CbzError, Result};
+use crate::filter::{FilterPolicy, SkipReason};
+use crate::ocr::OcrSidecar;
+use std::path::Path;
+
+/// A comic whatever its container: a zip, tar or 7z archive, an EPUB, or a
+/// folder.
+pub trait ComicSource {
+    /// Returns the pages in reading order.
+    fn pages(&mut self) -> Vec<String>;
+
+    fn read_page(&mut self, name: &str) -> Result<ImageEntry>;
+
+    /// Reads the page `name` like [`Self::read_page`], but only its first
+    /// `len` bytes, enough for the format and usually the size of the image.
+    ///
+    /// Sources that can stop early override this; by default the whole page
+    /// is read and cut.
+    fn read_page_header(&mut self, name: &str, len: u64) -> Result<ImageEntry> {
+        let mut entry = self.read_page(name)?;
+        entry.data.truncate(len as usize);
+        Ok(entry)
+    }
+
+    /// Parses `ComicInfo.xml`, if the source has one.
+    fn metadata(&mut self) -> Result<Option<ComicInfo>>;
+
+    /// Parses the embedded OCR results, see [`crate::ocr`]. Only zip archives
+    /// carry them.
+    fn ocr_sidecar(&mut self) -> Result<Option<OcrSidecar>> {
+        Ok(None)
+    }
+
+    /// Bytes taken on disk, the sum of the files for a folder.
+    fn size(&self) -> u64;
+
+    /// Bytes held in memory besides the file itself, such as decompressed
+    /// nested archives.
+    fn cached_size(&self) -> u64 {
+        0
+    }
+
+    /// Frees what it can of [`Self::cached_size`], read again when needed.
+    fn clear_cache(&mut self) {}
+
+    /// Groups the pages into chapters, by default one per subdirectory.
+    fn chapters(&mut self) -> Vec<Chapter> {
+        group_by_directory(self.pages())
+            .into_iter()
+            .map(|(_, chapter)| chapter)
+            .collect()
+    }
+}
+
+/// Container formats [`open_source`] recognizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    Zip,
+    Tar,
+    SevenZip,
+    Epub,
+    Directory,
+}
+
+impl SourceKind {
+    /// Guesses the container from the path: folders are folders, `.cbt` and
+    /// `.tar` are tar, `.cb7` and `.7z` are 7z, `.epub` is EPUB, and anything
+    /// else is a zip.
+    pub fn of(path: &Path) -> Self {
+        if path.is_dir() {
+            return SourceKind::Directory;
+        }
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("cbt" | "tar") => SourceKind::Tar,
+            Some("cb7" | "7z") => SourceKind::SevenZip,
+            Some("epub") => SourceKind::Epub,
+            _ => SourceKind::Zip,
+        }
+    }
+}
+
+/// Opens `path` with the source matching its [`SourceKind`].
+///
+/// Zip, EPUB and tar archives are read from disk on demand, 7z archives are
+/// decompressed into memory since they are usually solid.
+pub fn open_source(path: impl AsRef<Path>) -> Result<Box<dyn ComicSource + Send>> {
+    let path = path.as_ref();
+    Ok(match SourceKind::of(path) {
+        SourceKind::Zip => Box::new(CbzArchive::open(path)?),
+        SourceKind::Tar => Box::new(CbtArchive::open(path)?),
+        SourceKind::SevenZip => Box::new(Cb7Archive::open(path)?),
+        SourceKind::Epub => Box::new(EpubArchive::open(path)?),
+        SourceKind::Directory => Box::new(ComicDirectory::open(path)?),
+    })
+}
+
+/// A file of a source other than a zip, as listed when it is opened.
+#[derive(Debug, Clone)]
+pub(crate) struct SourceEntry {
+    pub name: String,
+    pub size: u64,
+    // Sniffed from the first bytes, `None` when unreadable.
+    pub format: Option<ImageFormat>,
+}
+
+/// Why `name` is not a page, or `None` when it is one.
+pub(crate) fn skip_reason(
+    filter: &FilterPolicy,
+    name: &str,
+    size: u64,
+    format: Option<ImageFormat>,
+) -> Option<SkipReason> {
+    if let Some(reason) = filter.check(name, size) {
+        return Some(reason);
+    }
+    let is_image = match format {
+        Some(format) => format != ImageFormat::Unknown,
+        None => has_image_extension(name),
+    };
+    (!is_image).then_some(SkipReason::NotAnImage)
+}
+
+/// Images of `entries` in natural order, or in the order of `info`'s pages.
+pub(crate) fn source_pages(
+    entries: &[SourceEntry],
+    filter: &FilterPolicy,
+    info: Option<&ComicInfo>,
+) -> Vec<String> {
+    let images = entries
+        .iter()
+        .filter(|entry| skip_reason(filter, &entry.name, entry.size, entry.format).is_none())
+        .map(|entry| entry.name.clone())
+        .collect();
+    reading_order(images, info)
+}
+
+/// Sorts `images` naturally, then moves the ones `info` lists to the front in
+/// its order.
+pub(crate) fn reading_order(mut images: Vec<String>, info: Option<&ComicInfo>) -> Vec<String> {
+    images.sort_by(|a, b| crate::natural_cmp(a, b));
+    let Some(info) = info else {
+        return images;
+    };
+
+    let mut taken = vec![false; images.len()];
+    let mut pages = Vec::with_capacity(images.len());
+    for index in info.pages.iter().map(|page| page.image) {
+        if index < images.len() && !taken[index] {
+            taken[index] = true;
+            pages.push(images[index].clone());
+        }
+    }
+    pages.extend(
+        images
+            .into_iter()
+            .zip(taken)
+            .filter(|(_, taken)| !taken)
+            .map(|(name, _)| name),
+    );
+    pages
+}
+
+/// Name of the `ComicInfo.xml` entry, matched case-insensitively.
+pub(crate) fn find_comic_info<'a>(
+    mut names: impl Iterator<Item = &'a String>,
+) -> Option<&'a String> {
+    names.find(|name| name.eq_ignore_ascii_case(COMIC_INFO_NAME))
+}
+
+pub(crate) fn parse_comic_info(data: &[u8]) -> Result<ComicInfo> {
+    ComicInfo::parse(&String::from_utf8_lossy(data))
+}
+
+pub(crate) fn image_entry(name: &str, data: Vec<u8>) -> Result<ImageEntry> {
+    let format = detect_image_format(&data)?;
+    Ok(ImageEntry {
+        name: name.to_string(),
+        data,
+        format,
+    })
+}
+
+pub(crate) fn not_found(name: &str) -> CbzError {
+    CbzError::NotFound(name.to_string())
+}
@@ -0,0 +1,34 @@
+//! Fixtures shared by the unit tests.
+
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// The signature of a JPEG, enough for an entry to be taken for one.
+pub(crate) const JPEG: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0];
+
+/// A JPEG signature followed by bytes that differ with `seed`, to tell pages
+/// apart when they are read back.
+pub(crate) fn page(seed: u8) -> Vec<u8> {
+    let mut data = JPEG.to_vec();
+    data.extend((0..200u16).map(|i| (i as u8).wrapping_mul(seed)));
+    data
+}
+
+/// A zip holding `entries` in that order, deflated.
+pub(crate) fn create_cbz(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    create_cbz_with(entries, CompressionMethod::Deflated)
+}
+
+/// A zip holding `entries` in that order, compressed with `method`.
+pub(crate) fn create_cbz_with(entries: &[(&str, &[u8])], method: CompressionMethod) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    let mut writer = ZipWriter::new(Cursor::new(&mut buffer));
+    let options = SimpleFileOptions::default().compression_method(method);
+    for (name, data) in entries {
+        writer.start_file(*name, options).unwrap();
+        writer.write_all(data).unwrap();
+    }
+    writer.finish().unwrap();
+    buffer
+}
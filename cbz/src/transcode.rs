@@ -0,0 +1,128 @@
+use crate::archive::{ImageEntry, ImageFormat};
+use crate::error::{CbzError, Result};
+use ravif::{Img, RGB8, RGBA8};
+
+/// Re-encodes pages when repacking, see [`crate::repack`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transcode {
+    pub format: TranscodeFormat,
+    /// From 1 to 100, higher is better looking and bigger.
+    pub quality: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscodeFormat {
+    WebP,
+    Avif,
+}
+
+impl TranscodeFormat {
+    fn image_format(self) -> ImageFormat {
+        match self {
+            TranscodeFormat::WebP => ImageFormat::WebP,
+            TranscodeFormat::Avif => ImageFormat::Avif,
+        }
+    }
+}
+
+impl Transcode {
+    /// Encodes `page` in the target format, or returns `None` to keep it as
+    /// it is: when it already is in that format, or is an AVIF or JPEG XL
+    /// page, which cannot be decoded.
+    pub fn apply(&self, page: &ImageEntry) -> Result<Option<Vec<u8>>> {
+        if matches!(page.format, ImageFormat::Avif | ImageFormat::Jxl)
+            || page.format == self.format.image_format()
+        {
+            return Ok(None);
+        }
+        let error = |reason: String| CbzError::Transcode {
+            name: page.name.clone(),
+            reason,
+        };
+        let image = image::load_from_memory(&page.data).map_err(|e| error(e.to_string()))?;
+        let quality = self.quality.clamp(1, 100);
+        let (width, height) = (image.width(), image.height());
+        // Scans rarely have transparency, and encoding without an alpha
+        // channel is smaller.
+        let alpha = image.color().has_alpha();
+
+        let data = match self.format {
+            TranscodeFormat::WebP if alpha => {
+                let rgba = image.to_rgba8();
+                webp::Encoder::from_rgba(&rgba, width, height)
+                    .encode(quality as f32)
+                    .to_vec()
+            }
+            TranscodeFormat::WebP => {
+                let rgb = image.to_rgb8();
+                webp::Encoder::from_rgb(&rgb, width, height)
+                    .encode(quality as f32)
+                    .to_vec()
+            }
+            TranscodeFormat::Avif => {
+                let encoder = ravif::Encoder::new().with_quality(quality as f32);
+                let (width, height) = (width as usize, height as usize);
+                let encoded = if alpha {
+                    let pixels: Vec<RGBA8> = image
+                        .to_rgba8()
+                        .pixels()
+                        .map(|p| RGBA8::new(p[0], p[1], p[2], p[3]))
+                        .collect();
+                    encoder.encode_rgba(Img::new(&pixels[..], width, height))
+                } else {
+                    let pixels: Vec<RGB8> = image
+                        .to_rgb8()
+                        .pixels()
+                        .map(|p| RGB8::new(p[0], p[1], p[2]))
+                        .collect();
+                    encoder.encode_rgb(Img::new(&pixels[..], width, height))
+                };
+                encoded.map_err(|e| error(e.to_string()))?.avif_file
+            }
+        };
+        Ok(Some(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::sniff;
+    use image::{DynamicImage, RgbImage};
+    use std::io::Cursor;
+
+    fn png_page() -> ImageEntry {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(16, 16, |x, y| {
+            image::Rgb([(x * 16) as u8, (y * 16) as u8, 128])
+        }));
+        let mut data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut data), image::ImageOutputFormat::Png)
+            .unwrap();
+        ImageEntry {
+            name: "page.png".to_string(),
+            data,
+            format: ImageFormat::Png,
+        }
+    }
+
+    #[test]
+    fn test_transcode() {
+        let page = png_page();
+        for format in [TranscodeFormat::WebP, TranscodeFormat::Avif] {
+            let transcode = Transcode {
+                format,
+                quality: 80,
+            };
+            let data = transcode.apply(&page).unwrap().unwrap();
+            assert_eq!(sniff(&data), format.image_format());
+
+            let already = ImageEntry {
+                name: page.name.clone(),
+                data,
+                format: format.image_format(),
+            };
+            assert_eq!(transcode.apply(&already).unwrap(), None);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::archive::{ImageFormat, detect_image_format};
+use crate::comic_info::{COMIC_INFO_NAME, ComicInfo};
+use crate::error::{CbzError, Result};
+use crate::ocr::{OCR_SIDECAR_NAME, OcrSidecar};
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Builds a CBZ from pages given in reading order.
+///
+/// Pages are named by their position, `001.jpg`, `002.png` and so on, with
+/// enough digits for `page_count` pages so that every reader sorts them the
+/// same way. An optional `ComicInfo.xml` and OCR sidecar are written last.
+pub struct CbzWriter<W: Write + Seek> {
+    zip: ZipWriter<W>,
+    width: usize,
+    pages: Vec<String>,
+    comic_info: Option<ComicInfo>,
+    ocr: Option<OcrSidecar>,
+}
+
+impl CbzWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, page_count: usize) -> Result<Self> {
+        let file = File::create(path.as_ref())?;
+        Ok(Self::new(BufWriter::new(file), page_count))
+    }
+}
+
+impl<W: Write + Seek> CbzWriter<W> {
+    /// Starts an archive of `page_count` pages. More can be added, but their
+    /// names may then sort before the first ones.
+    pub fn new(writer: W, page_count: usize) -> Self {
+        Self {
+            zip: ZipWriter::new(writer),
+            width: page_count.to_string().len().max(3),
+            pages: Vec::new(),
+            comic_info: None,
+            ocr: None,
+        }
+    }
+
+    /// Appends a page, returning the name it was stored under.
+    pub fn add_page(&mut self, data: &[u8]) -> Result<String> {
+        let format = detect_image_format(data)?;
+        if format == ImageFormat::Unknown {
+            return Err(CbzError::InvalidImageFormat);
+        }
+        let name = page_name(self.pages.len(), self.width, format);
+        // Pages are compressed images already.
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        self.zip.start_file(name.as_str(), options)?;
+        self.zip.write_all(data)?;
+        self.pages.push(name.clone());
+        Ok(name)
+    }
+
+    /// Sets the metadata written as `ComicInfo.xml`. Its `pages` index the
+    /// pages in the order they are added.
+    pub fn set_comic_info(&mut self, info: ComicInfo) {
+        self.comic_info = Some(info);
+    }
+
+    /// Sets the OCR results stored as [`OCR_SIDECAR_NAME`], keyed by the
+    /// names [`Self::add_page`] returns.
+    pub fn set_ocr_sidecar(&mut self, sidecar: OcrSidecar) {
+        self.ocr = Some(sidecar);
+    }
+
+    /// Names of the pages added so far.
+    pub fn pages(&self) -> &[String] {
+        &self.pages
+    }
+
+    /// Writes the metadata and the central directory.
+    pub fn finish(mut self) -> Result<W> {
+        if let Some(info) = &self.comic_info {
+            self.zip
+                .start_file(COMIC_INFO_NAME, SimpleFileOptions::default())?;
+            self.zip.write_all(info.to_xml().as_bytes())?;
+        }
+        if let Some(sidecar) = &self.ocr {
+            self.zip
+                .start_file(OCR_SIDECAR_NAME, SimpleFileOptions::default())?;
+            self.zip.write_all(&sidecar.to_json())?;
+        }
+        Ok(self.zip.finish()?)
+    }
+}
+
+fn page_name(index: usize, width: usize, format: ImageFormat) -> String {
+    format!(
+        "{:0width$}.{}",
+        index + 1,
+        format.extension(),
+        width = width
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CbzArchive;
+    use crate::comic_info::Manga;
+    use crate::test_util::JPEG;
+    use std::io::Cursor;
+
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    #[test]
+    fn test_write() {
+        let mut writer = CbzWriter::new(Cursor::new(Vec::new()), 1200);
+        assert_eq!(writer.add_page(JPEG).unwrap(), "0001.jpg");
+        assert_eq!(writer.add_page(PNG).unwrap(), "0002.png");
+        assert!(writer.add_page(b"not an image").is_err());
+        writer.set_comic_info(ComicInfo {
+            title: Some("Title".to_string()),
+            manga: Manga::YesAndRightToLeft,
+            ..ComicInfo::default()
+        });
+        let data = writer.finish().unwrap().into_inner();
+
+        let mut archive = CbzArchive::from_bytes(data).unwrap();
+        assert_eq!(archive.pages(), ["0001.jpg", "0002.png"]);
+        assert_eq!(archive.read_entry("0002.png").unwrap(), PNG);
+        let info = archive.metadata().unwrap().unwrap();
+        assert_eq!(info.title.as_deref(), Some("Title"));
+        assert!(info.is_right_to_left());
+    }
+
+    #[test]
+    fn test_page_name_width() {
+        assert_eq!(page_name(0, 3, ImageFormat::WebP), "001.webp");
+        assert_eq!(page_name(999, 3, ImageFormat::Jpeg), "1000.jpg");
+    }
+}
@@ -0,0 +1,435 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use cbz::{ComicSource, ImageFormat, OcrBlock, OcrPage, OcrSidecar, SourceKind};
+use clap::{Parser, ValueEnum};
+use comic_ocr::{
+    engine::{EngineOptions, OcrEngine},
+    pipeline::CancellationToken,
+};
+use image::DynamicImage;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+
+/// OCR whole comics: archives, folders of images, or folders of both.
+///
+/// Results are saved every few pages, so an interrupted run picks up close
+/// to where it stopped. Pages that already have results from the same models
+/// are skipped, as are AVIF pages, which the image crate is built without a
+/// decoder for.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Archives or folders, searched recursively
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Where the results are written
+    #[arg(short, long, value_enum, default_value_t = Output::Volume)]
+    output: Output,
+
+    /// Folder for the results, defaults to the folder of each volume
+    #[arg(long)]
+    out_dir: Option<PathBuf>,
+
+    /// Pages read and decoded in parallel, defaults to the number of CPUs
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Pages run through the models at once
+    #[arg(long, default_value_t = 2)]
+    workers: usize,
+
+    /// OCR pages again even if they already have results
+    #[arg(long, default_value_t = false)]
+    force: bool,
+
+    /// Use CPU instead of GPU
+    #[arg(long, default_value_t = false)]
+    cpu: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Output {
+    /// `<volume>.ocr.json`, in the format of the CBZ sidecar
+    Volume,
+    /// `<volume>.ocr/<page name>.json`, one file per page
+    Pages,
+    /// The sidecar embedded in the CBZ itself
+    Sidecar,
+}
+
+const ARCHIVE_EXTENSIONS: &[&str] = &["cbz", "zip", "cbt", "tar", "cb7", "7z", "epub"];
+
+// The volume JSON and the sidecar checkpoint hold every page, so they are
+// written after this many new pages or this long, not after each one.
+const CHECKPOINT_PAGES: usize = 32;
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct Summary {
+    volumes: usize,
+    failed_volumes: usize,
+    pages: usize,
+    skipped: usize,
+    unsupported: usize,
+    failed_pages: usize,
+    blocks: usize,
+}
+
+/// Results of one volume, read back from and written to the chosen output.
+struct Store {
+    output: Output,
+    volume: PathBuf,
+    // The volume JSON, the folder of page JSON, or for the sidecar a
+    // checkpoint kept until the results are embedded in the archive.
+    path: PathBuf,
+    sidecar: OcrSidecar,
+    force: bool,
+    changed: bool,
+    // Pages not yet in the file at `path`, and when it was last written.
+    unsaved: usize,
+    saved_at: Instant,
+}
+
+impl Store {
+    fn open(
+        output: Output,
+        volume: &Path,
+        out_dir: Option<&Path>,
+        force: bool,
+    ) -> anyhow::Result<Self> {
+        let dir = match out_dir {
+            Some(dir) => dir.to_path_buf(),
+            None => volume.parent().unwrap_or(Path::new(".")).to_path_buf(),
+        };
+        let name = volume_name(volume);
+        let path = match output {
+            Output::Volume => dir.join(format!("{name}.ocr.json")),
+            Output::Pages => dir.join(format!("{name}.ocr")),
+            Output::Sidecar => dir.join(format!("{name}.ocr.partial.json")),
+        };
+
+        let mut store = Self {
+            output,
+            volume: volume.to_path_buf(),
+            path,
+            sidecar: OcrSidecar::new(comic_ocr::MODEL_VERSION),
+            force,
+            changed: false,
+            unsaved: 0,
+            saved_at: Instant::now(),
+        };
+        if output == Output::Sidecar {
+            if SourceKind::of(volume) != SourceKind::Zip {
+                anyhow::bail!("only CBZ archives can hold the sidecar, use --output volume");
+            }
+            if !force {
+                let embedded = cbz::CbzArchive::open(volume)?.ocr_sidecar()?;
+                store.resume(embedded);
+            }
+        }
+        if !force && output != Output::Pages && store.path.exists() {
+            let saved = OcrSidecar::parse(&fs::read(&store.path)?)?;
+            store.resume(Some(saved));
+        }
+        Ok(store)
+    }
+
+    // Results of other models are OCR'd again.
+    fn resume(&mut self, saved: Option<OcrSidecar>) {
+        if let Some(saved) = saved.filter(|s| s.model_version == comic_ocr::MODEL_VERSION) {
+            self.sidecar.pages.extend(saved.pages);
+        }
+    }
+
+    fn is_done(&self, page_name: &str) -> bool {
+        if self.force {
+            return false;
+        }
+        match self.output {
+            Output::Pages => self.page_path(page_name).is_ok_and(|path| path.exists()),
+            Output::Volume | Output::Sidecar => self.sidecar.pages.contains_key(page_name),
+        }
+    }
+
+    fn save(&mut self, page_name: &str, page: OcrPage) -> anyhow::Result<()> {
+        self.changed = true;
+        if self.output == Output::Pages {
+            let path = self.page_path(page_name)?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            return write_atomic(&path, &serde_json::to_vec_pretty(&page)?);
+        }
+        self.sidecar.pages.insert(page_name.to_string(), page);
+        self.unsaved += 1;
+        if self.unsaved >= CHECKPOINT_PAGES || self.saved_at.elapsed() >= CHECKPOINT_INTERVAL {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    fn checkpoint(&mut self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(&self.path, &self.sidecar.to_json())?;
+        self.unsaved = 0;
+        self.saved_at = Instant::now();
+        Ok(())
+    }
+
+    /// Writes the pages not saved yet, and embeds the sidecar once every
+    /// page went through.
+    fn finish(mut self) -> anyhow::Result<()> {
+        match self.output {
+            Output::Pages => {}
+            Output::Volume if self.unsaved > 0 => self.checkpoint()?,
+            Output::Volume => {}
+            Output::Sidecar if self.changed => {
+                cbz::embed_ocr_sidecar(&self.volume, &self.sidecar)?;
+                if self.path.exists() {
+                    fs::remove_file(&self.path)?;
+                }
+            }
+            Output::Sidecar => {}
+        }
+        Ok(())
+    }
+
+    // Keyed on the whole page name, so `001.jpg` and `001.png` do not share a
+    // file, and never outside the folder whatever the archive names hold.
+    fn page_path(&self, page_name: &str) -> anyhow::Result<PathBuf> {
+        let path = cbz::names::safe_relative_path(&format!("{page_name}.json"))
+            .ok_or_else(|| anyhow::anyhow!("page name leaves the output folder"))?;
+        Ok(self.path.join(path))
+    }
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::WARN)
+        .init();
+
+    let args = Args::parse();
+
+    let mut paths = Vec::new();
+    for input in &args.inputs {
+        if !input.exists() {
+            anyhow::bail!("Not found: {:?}", input);
+        }
+        find_volumes(input, &mut paths)?;
+    }
+
+    let mut summary = Summary::default();
+    let mut volumes = Vec::new();
+    for path in paths {
+        match cbz::open_source(&path) {
+            Ok(mut source) => {
+                let pages = source.pages();
+                if !pages.is_empty() {
+                    volumes.push((path, pages));
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                summary.failed_volumes += 1;
+            }
+        }
+    }
+    summary.volumes = volumes.len() + summary.failed_volumes;
+    let total: usize = volumes.iter().map(|(_, pages)| pages.len()).sum();
+
+    let options = EngineOptions {
+        workers: args.workers,
+        ..EngineOptions::default()
+    };
+    let engine = tokio::runtime::Builder::new_current_thread()
+        .build()?
+        .block_on(OcrEngine::load(args.cpu, options))?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or(0))
+        .build()?;
+
+    let bar = ProgressBar::new(total as u64);
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{elapsed_precise} [{bar:40}] {pos}/{len} pages, {eta} left  {msg}",
+        )?
+        .progress_chars("=> "),
+    );
+
+    let summary = Mutex::new(summary);
+    for (path, pages) in &volumes {
+        bar.set_message(volume_name(path));
+        let opened = Store::open(args.output, path, args.out_dir.as_deref(), args.force)
+            .and_then(|store| Ok((store, cbz::open_source(path)?)));
+        let (store, source) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                bar.println(format!("{}: {:#}", path.display(), e));
+                bar.inc(pages.len() as u64);
+                summary.lock().unwrap().failed_volumes += 1;
+                continue;
+            }
+        };
+
+        let store = Mutex::new(store);
+        pool.install(|| process_volume(path, source, pages, &store, &engine, &bar, &summary));
+        if let Err(e) = store.into_inner().unwrap().finish() {
+            bar.println(format!("{}: {:#}", path.display(), e));
+            summary.lock().unwrap().failed_volumes += 1;
+        }
+    }
+    bar.finish_and_clear();
+
+    let summary = summary.into_inner().unwrap();
+    println!(
+        "Volumes: {} ({} failed)",
+        summary.volumes, summary.failed_volumes
+    );
+    println!(
+        "Pages: {} OCR'd, {} already done, {} unsupported, {} failed",
+        summary.pages, summary.skipped, summary.unsupported, summary.failed_pages
+    );
+    println!("Text blocks: {}", summary.blocks);
+
+    Ok(if summary.failed_volumes + summary.failed_pages > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn process_volume(
+    path: &Path,
+    source: Box<dyn ComicSource + Send>,
+    pages: &[String],
+    store: &Mutex<Store>,
+    engine: &OcrEngine,
+    bar: &ProgressBar,
+    summary: &Mutex<Summary>,
+) {
+    let todo: Vec<&String> = {
+        let store = store.lock().unwrap();
+        pages.iter().filter(|name| !store.is_done(name)).collect()
+    };
+    let skipped = pages.len() - todo.len();
+    summary.lock().unwrap().skipped += skipped;
+    bar.inc(skipped as u64);
+
+    let source = Mutex::new(source);
+    todo.par_iter().for_each(|name| {
+        // Reading needs the source to itself, decoding does not.
+        let entry = source.lock().unwrap().read_page(name);
+        if let Ok(entry) = &entry {
+            if entry.format == ImageFormat::Avif {
+                summary.lock().unwrap().unsupported += 1;
+                bar.inc(1);
+                return;
+            }
+        }
+        let page = entry
+            .map_err(anyhow::Error::from)
+            .and_then(|entry| Ok(image::load_from_memory(&entry.data)?))
+            .and_then(|image| recognize(engine, image));
+        let saved = page.and_then(|page| {
+            let blocks = page.blocks.len();
+            store.lock().unwrap().save(name, page)?;
+            Ok(blocks)
+        });
+
+        let mut summary = summary.lock().unwrap();
+        match saved {
+            Ok(blocks) => {
+                summary.pages += 1;
+                summary.blocks += blocks;
+            }
+            Err(e) => {
+                bar.println(format!("{} {}: {:#}", path.display(), name, e));
+                summary.failed_pages += 1;
+            }
+        }
+        bar.inc(1);
+    });
+}
+
+fn recognize(engine: &OcrEngine, image: DynamicImage) -> anyhow::Result<OcrPage> {
+    let (width, height) = (image.width(), image.height());
+    let blocks = engine.recognize(image, &CancellationToken::new())?;
+    Ok(OcrPage {
+        width,
+        height,
+        blocks: blocks
+            .into_iter()
+            .map(|block| OcrBlock {
+                text: block.text,
+                bbox: block.bbox.corners().map(|v| v as u32),
+                confidence: block.bbox.confidence,
+                ..OcrBlock::default()
+            })
+            .collect(),
+    })
+}
+
+/// Collects the volumes under `path`. A folder is one volume, with the
+/// images of its subfolders, unless it holds archives: then each archive is
+/// a volume and its subfolders are searched the same way.
+fn find_volumes(path: &Path, volumes: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    if !path.is_dir() || !contains_archive(path)? {
+        volumes.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by(|a, b| cbz::natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+    for entry in entries {
+        if entry.is_dir() || is_archive(&entry) {
+            find_volumes(&entry, volumes)?;
+        }
+    }
+    Ok(())
+}
+
+fn contains_archive(dir: &Path) -> anyhow::Result<bool> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if is_archive(&path) || (path.is_dir() && contains_archive(&path)?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn is_archive(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .is_some_and(|ext| ARCHIVE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+// Folder names are kept whole, as in "Vol. 1".
+fn volume_name(path: &Path) -> String {
+    let name = if path.is_dir() {
+        path.file_name()
+    } else {
+        path.file_stem()
+    };
+    name.map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn write_atomic(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let mut temp = path.as_os_str().to_os_string();
+    temp.push(".tmp");
+    fs::write(&temp, data)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
@@ -1,80 +1,184 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 use std::path::PathBuf;
 
-use clap::Parser;
-use comic_ocr::{comic_text_detector::ComicTextDetector, manga_ocr::MangaOcr};
-use serde::Serialize;
+use ab_glyph::FontArc;
+use clap::{Parser, Subcommand};
+use comic_ocr::{
+    comic_text_detector::ComicTextDetector,
+    engine::{EngineOptions, OcrEngine},
+    manga_ocr::MangaOcr,
+    output::{OutputFormat, PageResult},
+    pipeline::{crop, recognize_regions, CancellationToken},
+    server::{serve, Listen, ServeOptions},
+    visualize::{render, VisualizeOptions},
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the image file
-    #[arg(short, long)]
-    image: PathBuf,
+    #[arg(short, long, required = true)]
+    image: Option<PathBuf>,
 
     /// Use CPU instead of GPU
-    #[arg(long, default_value_t = false)]
+    #[arg(long, default_value_t = false, global = true)]
     cpu: bool,
+
+    /// Output format: json, hocr, alto or text
+    #[arg(short, long, default_value = "json")]
+    format: OutputFormat,
+
+    /// File to write the results to instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Also draw the boxes found on the page to this PNG
+    #[arg(long)]
+    visualize: Option<PathBuf>,
+
+    /// Font of the labels drawn by --visualize, one covering Japanese
+    #[arg(long, requires = "visualize")]
+    font: Option<PathBuf>,
+
+    /// Overlay the detector's text score on the page drawn by --visualize
+    #[arg(long, requires = "visualize")]
+    mask: bool,
+
+    /// Add a panel with each crop as the recognizer sees it to --visualize
+    #[arg(long, requires = "visualize")]
+    crops: bool,
 }
 
-#[derive(Serialize)]
-struct TextRegion {
-    text: String,
-    box_2d: [usize; 4], // [xmin, ymin, width, height]
-    confidence: f32,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Keep the models loaded and serve OCR requests over HTTP
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8765")]
+        listen: String,
+
+        /// Unix socket to listen on instead of an address
+        #[cfg(unix)]
+        #[arg(long)]
+        socket: Option<PathBuf>,
+
+        /// Requests processed at once, and pages run through the models
+        #[arg(long, default_value_t = 2)]
+        workers: usize,
+
+        /// Requests waiting for a worker before new ones are turned down
+        #[arg(long, default_value_t = 32)]
+        queue: usize,
+
+        /// Folder whose archives POST /ocr/page may read, repeatable;
+        /// without one only images sent in the request are read
+        #[arg(long = "root")]
+        roots: Vec<PathBuf>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::WARN)
+        .init();
 
     let args = Args::parse();
 
-    if !args.image.exists() {
-        anyhow::bail!("Image file not found: {:?}", args.image);
+    if let Some(Command::Serve {
+        listen,
+        #[cfg(unix)]
+        socket,
+        workers,
+        queue,
+        roots,
+    }) = &args.command
+    {
+        let listen = Listen::Tcp(listen.clone());
+        #[cfg(unix)]
+        let listen = socket.clone().map_or(listen, Listen::Unix);
+        let options = ServeOptions {
+            listen,
+            workers: *workers,
+            queue_size: *queue,
+            roots: roots.clone(),
+        };
+
+        let engine = OcrEngine::load(
+            args.cpu,
+            EngineOptions {
+                workers: *workers,
+                queue_size: *workers,
+            },
+        )
+        .await?;
+        return tokio::task::block_in_place(|| serve(engine, &options));
+    }
+
+    let image_path = args.image.as_ref().expect("required without a subcommand");
+    if !image_path.exists() {
+        anyhow::bail!("Image file not found: {:?}", image_path);
     }
 
     let detector = ComicTextDetector::load(args.cpu).await?;
     let ocr = MangaOcr::load(args.cpu).await?;
 
-    let image = image::open(&args.image)?;
-    
-    let bboxes = detector.inference(&image)?;
-
-    let mut crops = Vec::new();
-    let mut regions = Vec::new();
-
-    for bbox in &bboxes {
-        let width = bbox.xmax - bbox.xmin;
-        let height = bbox.ymax - bbox.ymin;
-        
-        // Use crop_imm to create an actual copy of the region
-        let crop = image.crop_imm(
-            bbox.xmin as u32, 
-            bbox.ymin as u32, 
-            width as u32, 
-            height as u32
-        );
-        
-        crops.push(crop);
-    }
+    let image = image::open(image_path)?;
+    let (bboxes, score_map) = if args.mask {
+        let (bboxes, score_map) = detector.inference_with_score_map(&image)?;
+        (bboxes, Some(score_map))
+    } else {
+        (detector.inference(&image)?, None)
+    };
+    let blocks = recognize_regions(&ocr, &image, bboxes, &CancellationToken::new())?;
 
-    if !crops.is_empty() {
-        let texts = ocr.inference(&crops)?;
-
-        for (bbox, text) in bboxes.iter().zip(texts.into_iter()) {
-             let width = bbox.xmax - bbox.xmin;
-             let height = bbox.ymax - bbox.ymin;
-             
-             regions.push(TextRegion {
-                 text,
-                 box_2d: [bbox.xmin as usize, bbox.ymin as usize, width as usize, height as usize],
-                 confidence: bbox.confidence,
-             });
-        }
-    }
+    let page = PageResult {
+        name: image_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        width: image.width(),
+        height: image.height(),
+        blocks,
+    };
 
-    let json = serde_json::to_string_pretty(&regions)?;
-    println!("{}", json);
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+    args.format
+        .writer()
+        .write(std::slice::from_ref(&page), &mut out)?;
+    out.flush()?;
+
+    if let Some(path) = &args.visualize {
+        let font = match &args.font {
+            Some(path) => Some(FontArc::try_from_vec(std::fs::read(path)?)?),
+            None => {
+                eprintln!("No --font given, the boxes are labeled without their text");
+                None
+            }
+        };
+        let crops: Vec<_> = if args.crops {
+            page.reading_order(true)
+                .into_iter()
+                .map(|block| ocr.preprocess_view(&crop(&image, &block.bbox)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let options = VisualizeOptions {
+            font: font.as_ref(),
+            mask: score_map.as_ref(),
+            crops: &crops,
+        };
+        render(&image, &page, &options).save(path)?;
+    }
 
     Ok(())
 }
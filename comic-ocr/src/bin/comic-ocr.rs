@@ -0,0 +1,3 @@
+//! The CLI as `comic-ocr`, the name `comic-ocr serve` is documented under,
+//! still built as `cli` for existing scripts.
+include!("cli.rs");
@@ -1,12 +1,13 @@
 mod yolo_v5;
 
 use burn::tensor::Tensor;
-use image::{DynamicImage, GenericImageView};
+use image::{DynamicImage, GenericImageView, GrayImage};
 //use std::path::PathBuf;
 use tracing::instrument;
 
 use crate::{device, weights::WeightedTokens, B};
 
+/// A detected text region, in pixels of the page, `xmax` and `ymax` excluded.
 #[derive(Debug, Clone)]
 pub struct Bbox<T> {
     pub xmin: T,
@@ -17,6 +18,13 @@ pub struct Bbox<T> {
     pub data: usize,
 }
 
+impl<T: Copy> Bbox<T> {
+    /// The box as `[xmin, ymin, xmax, ymax]`, the order every output uses.
+    pub fn corners(&self) -> [T; 4] {
+        [self.xmin, self.ymin, self.xmax, self.ymax]
+    }
+}
+
 pub struct ComicTextDetector {
     yolo: yolo_v5::YoloV5,
 }
@@ -53,6 +61,29 @@ impl ComicTextDetector {
 
         Ok(bboxes)
     }
+
+    /// Like [`ComicTextDetector::inference`], along with how likely the model
+    /// finds text in each part of the page, for debugging.
+    ///
+    /// This port has no segmentation head, so the map comes from the
+    /// detection head: the best score of the predictions centered in each
+    /// 8x8 cell of the model input, before any threshold, stretched to the
+    /// page.
+    #[instrument(level = "debug", skip_all)]
+    pub fn inference_with_score_map(
+        &self,
+        image: &DynamicImage,
+    ) -> anyhow::Result<(Vec<Bbox<usize>>, GrayImage)> {
+        let original_dimensions = image.dimensions();
+        let (image_tensor, resized_dimensions) = preprocess(image)?;
+
+        let (predictions, _features) = self.yolo.forward(image_tensor)?;
+
+        let bboxes = postprocess_yolo(&predictions, original_dimensions, resized_dimensions)?;
+        let map = score_map(&predictions, original_dimensions, resized_dimensions);
+
+        Ok((bboxes, map))
+    }
 }
 
 fn preprocess(image: &DynamicImage) -> anyhow::Result<(Tensor<B, 4>, (u32, u32))> {
@@ -181,6 +212,37 @@ fn postprocess_yolo(
     Ok(result)
 }
 
+fn score_map(
+    predictions: &Tensor<B, 3>,
+    original_dimensions: (u32, u32),
+    resized_dimensions: (u32, u32),
+) -> GrayImage {
+    // Stride of the finest of the three detection scales.
+    const CELL: u32 = 8;
+
+    let (orig_w, orig_h) = original_dimensions;
+    let (resized_w, resized_h) = resized_dimensions;
+    let mut map = GrayImage::new(resized_w.div_ceil(CELL), resized_h.div_ceil(CELL));
+
+    let [_batch, num_boxes, num_outputs] = predictions.dims();
+    let flat_data: Vec<f32> = predictions.clone().to_data().to_vec().unwrap_or_default();
+    for i in 0..num_boxes {
+        let prediction = &flat_data[i * num_outputs..(i + 1) * num_outputs];
+        let max_class_score = prediction[5..].iter().copied().fold(0.0f32, f32::max);
+        let score = (prediction[4] * max_class_score * 255.0) as u8;
+
+        // Centers left of or above the input saturate to the first cell.
+        let x = prediction[0] as u32 / CELL;
+        let y = prediction[1] as u32 / CELL;
+        if x < map.width() && y < map.height() {
+            let pixel = map.get_pixel_mut(x, y);
+            pixel[0] = pixel[0].max(score);
+        }
+    }
+
+    image::imageops::resize(&map, orig_w, orig_h, image::imageops::FilterType::Triangle)
+}
+
 fn non_maximum_suppression(boxes: &mut Vec<Bbox<usize>>, threshold: f32) {
     // Sort by confidence descending
     boxes.sort_by(|a, b| {
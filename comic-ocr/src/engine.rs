@@ -0,0 +1,130 @@
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::Context;
+use image::DynamicImage;
+
+use crate::{
+    comic_text_detector::ComicTextDetector,
+    manga_ocr::MangaOcr,
+    pipeline::{recognize_page, CancellationToken, TextBlock},
+};
+
+// Inference takes `&self`, so the workers share one copy of the models.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<ComicTextDetector>();
+    assert_send_sync::<MangaOcr>();
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct EngineOptions {
+    /// Pages run through the models at once.
+    pub workers: usize,
+    /// Pages waiting for a worker before [`OcrEngine::recognize`] blocks.
+    pub queue_size: usize,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        Self {
+            workers: 2,
+            queue_size: 16,
+        }
+    }
+}
+
+struct Models {
+    detector: ComicTextDetector,
+    ocr: MangaOcr,
+}
+
+struct Job {
+    image: DynamicImage,
+    cancel: CancellationToken,
+    reply: Sender<anyhow::Result<Vec<TextBlock>>>,
+}
+
+/// Handle to the loaded models, shared by cloning, which OCRs pages on a
+/// bounded pool of worker threads.
+///
+/// The workers exit once every handle is dropped.
+#[derive(Clone)]
+pub struct OcrEngine {
+    sender: SyncSender<Job>,
+    workers: usize,
+}
+
+impl OcrEngine {
+    pub async fn load(use_cpu: bool, options: EngineOptions) -> anyhow::Result<Self> {
+        let detector = ComicTextDetector::load(use_cpu)
+            .await
+            .context("failed to load the text detector")?;
+        let ocr = MangaOcr::load(use_cpu)
+            .await
+            .context("failed to load manga-ocr")?;
+        Self::new(detector, ocr, options)
+    }
+
+    pub fn new(
+        detector: ComicTextDetector,
+        ocr: MangaOcr,
+        options: EngineOptions,
+    ) -> anyhow::Result<Self> {
+        let models = Arc::new(Models { detector, ocr });
+        let (sender, receiver) = mpsc::sync_channel(options.queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = options.workers.max(1);
+        for index in 0..workers {
+            let (models, receiver) = (models.clone(), receiver.clone());
+            thread::Builder::new()
+                .name(format!("ocr-engine-{index}"))
+                .stack_size(4 * 1024 * 1024) // 4MB stack for inference
+                .spawn(move || run_worker(&models, &receiver))
+                .context("failed to spawn an OCR worker")?;
+        }
+        Ok(Self { sender, workers })
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Detects the text regions of `image` and recognizes each of them, as
+    /// [`recognize_page`] does, once a worker is free.
+    ///
+    /// Blocks until the page is done, or fails with
+    /// [`crate::pipeline::Cancelled`] once `cancel` fires.
+    pub fn recognize(
+        &self,
+        image: DynamicImage,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Vec<TextBlock>> {
+        let (reply, response) = mpsc::channel();
+        let job = Job {
+            image,
+            cancel: cancel.clone(),
+            reply,
+        };
+        self.sender
+            .send(job)
+            .map_err(|_| anyhow::anyhow!("the OCR workers stopped"))?;
+        response
+            .recv()
+            .map_err(|_| anyhow::anyhow!("the OCR worker stopped"))?
+    }
+}
+
+fn run_worker(models: &Models, receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        // Only one idle worker waits on the queue at a time.
+        let Ok(job) = receiver.lock().unwrap().recv() else {
+            return;
+        };
+        // A page cancelled while queued stops at the first check.
+        let blocks = recognize_page(&models.detector, &models.ocr, &job.image, &job.cancel);
+        // The caller may have given up waiting.
+        let _ = job.reply.send(blocks);
+    }
+}
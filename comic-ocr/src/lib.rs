@@ -2,10 +2,19 @@
 mod weights;
 
 pub mod comic_text_detector;
+pub mod engine;
 pub mod manga_ocr;
+pub mod output;
+pub mod pipeline;
+pub mod server;
+pub mod visualize;
 
 //pub use hf_hub::set_cache_dir;
 
+/// Identifies the detection and recognition models, whose weights are built
+/// into the crate, so results are only reused by the same models.
+pub const MODEL_VERSION: &str = concat!("comic-ocr ", env!("CARGO_PKG_VERSION"));
+
 pub type B = burn::backend::Wgpu<f32>;
 
 pub type Dev = <B as burn::tensor::backend::Backend>::Device;
@@ -98,16 +98,22 @@ impl MangaOcr {
         Ok(results)
     }
 
-    fn preprocess_image(&self, img: &image::DynamicImage) -> Result<Tensor<B, 4>> {
-        let size = self.preprocessor.size as usize;
+    /// `img` as the model sees it, before normalization: in grayscale and
+    /// stretched to the input size.
+    pub fn preprocess_view(&self, img: &image::DynamicImage) -> image::DynamicImage {
+        let size = self.preprocessor.size;
         // Convert to grayscale first, then to RGB (all channels will have same value)
         let resized = img.grayscale().to_rgb8();
-        let resized = image::DynamicImage::ImageRgb8(resized).resize_exact(
-            size as u32,
-            size as u32,
+        image::DynamicImage::ImageRgb8(resized).resize_exact(
+            size,
+            size,
             image::imageops::FilterType::Triangle,
-        );
-        let rgb = resized.to_rgb8();
+        )
+    }
+
+    fn preprocess_image(&self, img: &image::DynamicImage) -> Result<Tensor<B, 4>> {
+        let size = self.preprocessor.size as usize;
+        let rgb = self.preprocess_view(img).to_rgb8();
 
         let mean = self.preprocessor.image_mean;
         let std = self.preprocessor.image_std;
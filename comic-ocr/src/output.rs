@@ -0,0 +1,540 @@
+//! Writers turning OCR results into files other tools read.
+//!
+//! Boxes are `[xmin, ymin, xmax, ymax]` in pixels of the page everywhere,
+//! see [`Bbox::corners`]. hOCR uses the same order; ALTO describes a box by
+//! its position and size, which is derived when writing.
+//!
+//! # JSON
+//!
+//! [`JsonWriter`] writes version [`JSON_VERSION`] of this schema, also
+//! available as `schema/ocr-output.schema.json`:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "model_version": "comic-ocr 0.1.0",
+//!   "pages": [
+//!     {
+//!       "name": "001.jpg",
+//!       "width": 1200,
+//!       "height": 1700,
+//!       "blocks": [
+//!         { "text": "…", "bbox": [812, 96, 880, 402], "confidence": 0.91 }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Fields may be added within a version; a new version means existing fields
+//! changed meaning or went away.
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::comic_text_detector::Bbox;
+use crate::pipeline::TextBlock;
+
+/// Version of the JSON written by [`JsonWriter`].
+pub const JSON_VERSION: u32 = 1;
+
+/// The results of one page.
+#[derive(Debug, Clone)]
+pub struct PageResult {
+    /// Name of the image, such as its path in the archive.
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub blocks: Vec<TextBlock>,
+}
+
+impl PageResult {
+    /// Blocks in reading order: rows of blocks from top to bottom, each row
+    /// from right to left as in manga, or left to right.
+    ///
+    /// Blocks are in the same row when they overlap vertically by at least
+    /// half of the shorter one.
+    pub fn reading_order(&self, right_to_left: bool) -> Vec<&TextBlock> {
+        let mut blocks: Vec<&TextBlock> = self.blocks.iter().collect();
+        blocks.sort_by_key(|block| block.bbox.ymin);
+
+        let mut rows: Vec<Vec<&TextBlock>> = Vec::new();
+        for block in blocks {
+            match rows.last_mut() {
+                Some(row) if row.iter().any(|other| same_row(&other.bbox, &block.bbox)) => {
+                    row.push(block)
+                }
+                _ => rows.push(vec![block]),
+            }
+        }
+
+        for row in &mut rows {
+            if right_to_left {
+                row.sort_by_key(|block| std::cmp::Reverse(block.bbox.xmax));
+            } else {
+                row.sort_by_key(|block| block.bbox.xmin);
+            }
+        }
+        rows.into_iter().flatten().collect()
+    }
+}
+
+fn same_row(a: &Bbox<usize>, b: &Bbox<usize>) -> bool {
+    let overlap = a.ymax.min(b.ymax).saturating_sub(a.ymin.max(b.ymin));
+    let shorter = (a.ymax - a.ymin).min(b.ymax - b.ymin);
+    overlap * 2 >= shorter
+}
+
+/// Writes the results of one or more pages in some format.
+pub trait OutputWriter {
+    /// Extension of the files written, without the dot.
+    fn extension(&self) -> &'static str;
+
+    fn write(&self, pages: &[PageResult], out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// The formats comic-ocr writes, parsed from their lowercase name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Hocr,
+    Alto,
+    Text,
+}
+
+impl OutputFormat {
+    /// Writer for the format, with blocks read right to left.
+    pub fn writer(self) -> Box<dyn OutputWriter> {
+        match self {
+            OutputFormat::Json => Box::new(JsonWriter),
+            OutputFormat::Hocr => Box::new(HocrWriter),
+            OutputFormat::Alto => Box::new(AltoWriter),
+            OutputFormat::Text => Box::new(TextWriter {
+                right_to_left: true,
+            }),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "hocr" => Ok(OutputFormat::Hocr),
+            "alto" => Ok(OutputFormat::Alto),
+            "text" | "txt" => Ok(OutputFormat::Text),
+            _ => Err(format!(
+                "unknown format {s}, expected json, hocr, alto or text"
+            )),
+        }
+    }
+}
+
+/// The versioned JSON described in the [module documentation](self).
+pub struct JsonWriter;
+
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    version: u32,
+    model_version: &'a str,
+    pages: Vec<JsonPage<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonPage<'a> {
+    name: &'a str,
+    width: u32,
+    height: u32,
+    blocks: Vec<JsonBlock<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonBlock<'a> {
+    text: &'a str,
+    bbox: [usize; 4],
+    confidence: f32,
+}
+
+impl OutputWriter for JsonWriter {
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn write(&self, pages: &[PageResult], out: &mut dyn Write) -> io::Result<()> {
+        let output = JsonOutput {
+            version: JSON_VERSION,
+            model_version: crate::MODEL_VERSION,
+            pages: pages
+                .iter()
+                .map(|page| JsonPage {
+                    name: &page.name,
+                    width: page.width,
+                    height: page.height,
+                    blocks: page
+                        .reading_order(true)
+                        .into_iter()
+                        .map(|block| JsonBlock {
+                            text: &block.text,
+                            bbox: block.bbox.corners(),
+                            confidence: block.bbox.confidence,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        };
+        serde_json::to_writer_pretty(&mut *out, &output)?;
+        writeln!(out)
+    }
+}
+
+/// hOCR, HTML with one `ocr_carea` per block holding a single line.
+pub struct HocrWriter;
+
+impl OutputWriter for HocrWriter {
+    fn extension(&self) -> &'static str {
+        "hocr"
+    }
+
+    fn write(&self, pages: &[PageResult], out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            out,
+            r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd">"#
+        )?;
+        writeln!(
+            out,
+            r#"<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="ja" lang="ja">"#
+        )?;
+        writeln!(out, " <head>")?;
+        writeln!(out, "  <title></title>")?;
+        writeln!(
+            out,
+            r#"  <meta http-equiv="Content-Type" content="text/html;charset=utf-8"/>"#
+        )?;
+        writeln!(
+            out,
+            r#"  <meta name="ocr-system" content="{}"/>"#,
+            escape(crate::MODEL_VERSION)
+        )?;
+        writeln!(
+            out,
+            r#"  <meta name="ocr-capabilities" content="ocr_page ocr_carea ocr_line ocrx_word"/>"#
+        )?;
+        writeln!(out, " </head>")?;
+        writeln!(out, " <body>")?;
+
+        for (p, page) in pages.iter().enumerate() {
+            let p = p + 1;
+            writeln!(
+                out,
+                r#"  <div class="ocr_page" id="page_{p}" title="image &quot;{}&quot;; bbox 0 0 {} {}; ppageno {}">"#,
+                escape(&page.name),
+                page.width,
+                page.height,
+                p - 1
+            )?;
+            for (b, block) in page.reading_order(true).into_iter().enumerate() {
+                let b = b + 1;
+                let [xmin, ymin, xmax, ymax] = block.bbox.corners();
+                let bbox = format!("bbox {xmin} {ymin} {xmax} {ymax}");
+                let confidence = (block.bbox.confidence * 100.0).round();
+                writeln!(
+                    out,
+                    r#"   <div class="ocr_carea" id="block_{p}_{b}" title="{bbox}">"#
+                )?;
+                writeln!(
+                    out,
+                    r#"    <span class="ocr_line" id="line_{p}_{b}" title="{bbox}"><span class="ocrx_word" id="word_{p}_{b}" title="{bbox}; x_wconf {confidence}">{}</span></span>"#,
+                    escape(&block.text)
+                )?;
+                writeln!(out, "   </div>")?;
+            }
+            writeln!(out, "  </div>")?;
+        }
+
+        writeln!(out, " </body>")?;
+        writeln!(out, "</html>")
+    }
+}
+
+/// ALTO 4 XML, with one `TextBlock` per block holding a single line.
+pub struct AltoWriter;
+
+impl OutputWriter for AltoWriter {
+    fn extension(&self) -> &'static str {
+        "xml"
+    }
+
+    fn write(&self, pages: &[PageResult], out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            out,
+            r#"<alto xmlns="http://www.loc.gov/standards/alto/ns-v4#" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://www.loc.gov/standards/alto/ns-v4# http://www.loc.gov/alto/v4/alto-4-2.xsd">"#
+        )?;
+        writeln!(out, "  <Description>")?;
+        writeln!(out, "    <MeasurementUnit>pixel</MeasurementUnit>")?;
+        if let [page] = pages {
+            writeln!(out, "    <sourceImageInformation>")?;
+            writeln!(out, "      <fileName>{}</fileName>", escape(&page.name))?;
+            writeln!(out, "    </sourceImageInformation>")?;
+        }
+        writeln!(out, r#"    <OCRProcessing ID="OCR_0">"#)?;
+        writeln!(out, "      <ocrProcessingStep>")?;
+        writeln!(out, "        <processingSoftware>")?;
+        writeln!(out, "          <softwareName>comic-ocr</softwareName>")?;
+        writeln!(
+            out,
+            "          <softwareVersion>{}</softwareVersion>",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(out, "        </processingSoftware>")?;
+        writeln!(out, "      </ocrProcessingStep>")?;
+        writeln!(out, "    </OCRProcessing>")?;
+        writeln!(out, "  </Description>")?;
+        writeln!(out, "  <Layout>")?;
+
+        for (p, page) in pages.iter().enumerate() {
+            let p = p + 1;
+            let size = format!(r#"WIDTH="{}" HEIGHT="{}""#, page.width, page.height);
+            writeln!(
+                out,
+                r#"    <Page ID="page_{p}" PHYSICAL_IMG_NR="{p}" {size}>"#
+            )?;
+            writeln!(out, r#"      <PrintSpace HPOS="0" VPOS="0" {size}>"#)?;
+            for (b, block) in page.reading_order(true).into_iter().enumerate() {
+                let b = b + 1;
+                let [xmin, ymin, xmax, ymax] = block.bbox.corners();
+                let position = format!(
+                    r#"HPOS="{xmin}" VPOS="{ymin}" WIDTH="{}" HEIGHT="{}""#,
+                    xmax - xmin,
+                    ymax - ymin
+                );
+                writeln!(out, r#"        <TextBlock ID="block_{p}_{b}" {position}>"#)?;
+                writeln!(out, r#"          <TextLine ID="line_{p}_{b}" {position}>"#)?;
+                writeln!(
+                    out,
+                    r#"            <String ID="string_{p}_{b}" {position} CONTENT="{}" WC="{:.2}"/>"#,
+                    escape(&block.text),
+                    block.bbox.confidence
+                )?;
+                writeln!(out, "          </TextLine>")?;
+                writeln!(out, "        </TextBlock>")?;
+            }
+            writeln!(out, "      </PrintSpace>")?;
+            writeln!(out, "    </Page>")?;
+        }
+
+        writeln!(out, "  </Layout>")?;
+        writeln!(out, "</alto>")
+    }
+}
+
+/// The text alone, one block per line in reading order, with a form feed
+/// between pages.
+pub struct TextWriter {
+    pub right_to_left: bool,
+}
+
+impl OutputWriter for TextWriter {
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn write(&self, pages: &[PageResult], out: &mut dyn Write) -> io::Result<()> {
+        for (p, page) in pages.iter().enumerate() {
+            if p > 0 {
+                writeln!(out, "\x0c")?;
+            }
+            for block in page.reading_order(self.right_to_left) {
+                writeln!(out, "{}", block.text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(text: &str, [xmin, ymin, xmax, ymax]: [usize; 4]) -> TextBlock {
+        TextBlock {
+            bbox: Bbox {
+                xmin,
+                xmax,
+                ymin,
+                ymax,
+                confidence: 0.91,
+                data: 0,
+            },
+            text: text.to_string(),
+        }
+    }
+
+    /// Two columns side by side, the left one starting a little lower, and a
+    /// caption under both.
+    fn page() -> PageResult {
+        PageResult {
+            name: "001.jpg".to_string(),
+            width: 1200,
+            height: 1700,
+            blocks: vec![
+                block("caption", [100, 1400, 1100, 1500]),
+                block("left", [100, 140, 180, 400]),
+                block("right", [800, 100, 880, 420]),
+            ],
+        }
+    }
+
+    fn write(writer: &dyn OutputWriter, pages: &[PageResult]) -> String {
+        let mut out = Vec::new();
+        writer.write(pages, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn texts(blocks: Vec<&TextBlock>) -> Vec<&str> {
+        blocks
+            .into_iter()
+            .map(|block| block.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_reading_order() {
+        let page = page();
+        assert_eq!(
+            texts(page.reading_order(true)),
+            ["right", "left", "caption"]
+        );
+        assert_eq!(
+            texts(page.reading_order(false)),
+            ["left", "right", "caption"]
+        );
+
+        // Blocks barely overlapping vertically are in different rows.
+        let mut page = page;
+        page.blocks[1] = block("left", [100, 380, 180, 700]);
+        assert_eq!(
+            texts(page.reading_order(true)),
+            ["right", "left", "caption"]
+        );
+        assert_eq!(
+            texts(page.reading_order(false)),
+            ["right", "left", "caption"]
+        );
+    }
+
+    #[test]
+    fn test_hocr() {
+        let hocr = write(&HocrWriter, &[page(), page()]);
+        assert_eq!(hocr.matches(r#"class="ocr_page""#).count(), 2);
+        assert_eq!(hocr.matches(r#"class="ocr_carea""#).count(), 6);
+        assert_eq!(hocr.matches(r#"class="ocrx_word""#).count(), 6);
+        assert!(hocr.contains(
+            r#"id="page_2" title="image &quot;001.jpg&quot;; bbox 0 0 1200 1700; ppageno 1""#
+        ));
+        // Blocks are numbered in reading order, with their corners.
+        assert!(hocr.contains(r#"id="block_1_1" title="bbox 800 100 880 420""#));
+        assert!(hocr.contains(r#"id="block_1_2" title="bbox 100 140 180 400""#));
+        assert!(hocr.contains(
+            r#"<span class="ocrx_word" id="word_1_1" title="bbox 800 100 880 420; x_wconf 91">right</span>"#
+        ));
+        assert!(hocr.trim_end().ends_with("</html>"));
+    }
+
+    #[test]
+    fn test_alto() {
+        let alto = write(&AltoWriter, &[page()]);
+        assert!(alto.contains("<fileName>001.jpg</fileName>"));
+        assert!(
+            alto.contains(r#"<Page ID="page_1" PHYSICAL_IMG_NR="1" WIDTH="1200" HEIGHT="1700">"#)
+        );
+        assert_eq!(alto.matches("<TextBlock ").count(), 3);
+        assert_eq!(alto.matches("</TextBlock>").count(), 3);
+        // Positions and sizes rather than corners.
+        assert!(alto.contains(
+            r#"<TextBlock ID="block_1_1" HPOS="800" VPOS="100" WIDTH="80" HEIGHT="320">"#
+        ));
+        assert!(alto.contains(
+            r#"<String ID="string_1_3" HPOS="100" VPOS="1400" WIDTH="1000" HEIGHT="100" CONTENT="caption" WC="0.91"/>"#
+        ));
+        assert!(alto.trim_end().ends_with("</alto>"));
+
+        // The source image is only named for a single page.
+        let alto = write(&AltoWriter, &[page(), page()]);
+        assert!(!alto.contains("<fileName>"));
+        assert_eq!(alto.matches("<Page ").count(), 2);
+    }
+
+    #[test]
+    fn test_escape() {
+        let mut page = page();
+        page.name = "a&b.jpg".to_string();
+        page.blocks = vec![block(r#"<"それ">"#, [0, 0, 10, 10])];
+        let hocr = write(&HocrWriter, &[page.clone()]);
+        assert!(hocr.contains("a&amp;b.jpg"));
+        assert!(hocr.contains(">&lt;&quot;それ&quot;&gt;</span>"));
+        let alto = write(&AltoWriter, &[page]);
+        assert!(alto.contains(r#"CONTENT="&lt;&quot;それ&quot;&gt;""#));
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let json: serde_json::Value = serde_json::from_str(&write(&JsonWriter, &[page()])).unwrap();
+        let schema: serde_json::Value =
+            serde_json::from_str(include_str!("../schema/ocr-output.schema.json")).unwrap();
+
+        assert_eq!(json["version"], JSON_VERSION);
+        assert_eq!(schema["properties"]["version"]["const"], JSON_VERSION);
+        assert_eq!(json["model_version"], crate::MODEL_VERSION);
+
+        // Every field the schema requires is written, and nothing else.
+        let keys = |value: &serde_json::Value| {
+            let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        let required = |value: &serde_json::Value| {
+            let mut keys: Vec<String> = serde_json::from_value(value["required"].clone()).unwrap();
+            keys.sort();
+            keys
+        };
+        let page = &json["pages"][0];
+        let block = &page["blocks"][0];
+        assert_eq!(keys(&json), required(&schema));
+        assert_eq!(keys(page), required(&schema["$defs"]["page"]));
+        assert_eq!(keys(block), required(&schema["$defs"]["block"]));
+
+        assert_eq!(page["name"], "001.jpg");
+        assert_eq!(page["width"], 1200);
+        assert_eq!(page["height"], 1700);
+        let texts: Vec<&str> = page["blocks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|block| block["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(texts, ["right", "left", "caption"]);
+        assert_eq!(block["bbox"], serde_json::json!([800, 100, 880, 420]));
+        assert!((block["confidence"].as_f64().unwrap() - 0.91).abs() < 1e-6);
+    }
+}
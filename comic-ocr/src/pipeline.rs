@@ -0,0 +1,100 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use image::DynamicImage;
+
+use crate::{
+    comic_text_detector::{Bbox, ComicTextDetector},
+    manga_ocr::MangaOcr,
+};
+
+/// Shared flag used to stop a page that nobody is waiting for anymore.
+///
+/// Cancellation is cooperative: it is checked after detection and before
+/// each crop is recognized, so at most one model call runs after `cancel`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn check(&self) -> anyhow::Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when a [`CancellationToken`] stopped the pipeline.
+#[derive(Debug, Clone, Copy)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OCR cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[derive(Debug, Clone)]
+pub struct TextBlock {
+    pub bbox: Bbox<usize>,
+    pub text: String,
+}
+
+/// Detects the text regions of a page and recognizes each of them.
+pub fn recognize_page(
+    detector: &ComicTextDetector,
+    ocr: &MangaOcr,
+    image: &DynamicImage,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<TextBlock>> {
+    cancel.check()?;
+    let bboxes = detector.inference(image)?;
+    recognize_regions(ocr, image, bboxes, cancel)
+}
+
+/// Recognizes the text regions `bboxes` of a page, as found by
+/// [`ComicTextDetector::inference`].
+pub fn recognize_regions(
+    ocr: &MangaOcr,
+    image: &DynamicImage,
+    bboxes: Vec<Bbox<usize>>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<TextBlock>> {
+    let mut blocks = Vec::with_capacity(bboxes.len());
+    for bbox in bboxes {
+        cancel.check()?;
+
+        let text = ocr
+            .inference(&[crop(image, &bbox)])?
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        blocks.push(TextBlock { bbox, text });
+    }
+
+    Ok(blocks)
+}
+
+/// The part of `image` inside `bbox`.
+pub fn crop(image: &DynamicImage, bbox: &Bbox<usize>) -> DynamicImage {
+    image.crop_imm(
+        bbox.xmin as u32,
+        bbox.ymin as u32,
+        (bbox.xmax - bbox.xmin) as u32,
+        (bbox.ymax - bbox.ymin) as u32,
+    )
+}
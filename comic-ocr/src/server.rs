@@ -0,0 +1,523 @@
+//! A local HTTP API keeping the models loaded, so scripts and other readers
+//! share one warm instance instead of loading them per image, started with
+//! `comic-ocr serve [--listen ADDR | --socket PATH] [--root DIR]...`.
+//!
+//! Routes:
+//!
+//! - `GET /health`: `{"status": "ok", "model_version", "workers", "queued",
+//!   "running", "served", "failed"}`, answered right away even when every
+//!   worker is busy.
+//! - `POST /ocr`: the body is an image, answered with the results of one
+//!   page named `image`.
+//! - `POST /ocr/page`: the body is `{"path": "comic.cbz", "page": "001.jpg"}`
+//!   for any archive or folder [`cbz::open_source`] reads, with `page` either
+//!   a name or an index from 0. Only paths inside [`ServeOptions::roots`] are
+//!   read, others get `403 Forbidden`, so that any program able to reach the
+//!   server cannot read every file of the user.
+//!
+//! Results are the JSON of [`crate::output`], or another format with
+//! `?format=hocr`, `alto` or `text`. Errors are `{"error": "…"}`, with
+//! `415 Unsupported Media Type` for images the image crate cannot decode,
+//! such as AVIF.
+//!
+//! OCR requests wait in a queue of bounded size for one of the workers; when
+//! it is full they are turned down with `503 Service Unavailable`.
+
+use std::fmt;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use image::DynamicImage;
+use serde::Deserialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::engine::OcrEngine;
+use crate::output::{OutputFormat, PageResult};
+use crate::pipeline::CancellationToken;
+
+/// Largest request body read, which bounds the memory of a request.
+const MAX_BODY_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Where the server listens.
+#[derive(Debug, Clone)]
+pub enum Listen {
+    /// A TCP address such as `127.0.0.1:8765`.
+    Tcp(String),
+    /// A unix socket, replacing a stale one left at the path.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl fmt::Display for Listen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listen::Tcp(addr) => write!(f, "http://{addr}"),
+            #[cfg(unix)]
+            Listen::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    pub listen: Listen,
+    /// Requests read and decoded at once, which then wait for one of the
+    /// engine's workers.
+    pub workers: usize,
+    /// Requests waiting for a worker before new ones are turned down.
+    pub queue_size: usize,
+    /// Folders whose archives `POST /ocr/page` reads, none when empty.
+    pub roots: Vec<PathBuf>,
+}
+
+#[derive(Default)]
+struct Stats {
+    queued: AtomicUsize,
+    running: AtomicUsize,
+    served: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+    fn new(status: u16, message: impl fmt::Display) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for HttpError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::new(500, format!("{e:#}"))
+    }
+}
+
+#[derive(Deserialize)]
+struct PageRequest {
+    path: PathBuf,
+    page: PageRef,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PageRef {
+    Index(usize),
+    Name(String),
+}
+
+/// Serves OCR requests until the process is stopped.
+pub fn serve(engine: OcrEngine, options: &ServeOptions) -> anyhow::Result<()> {
+    let server = match &options.listen {
+        Listen::Tcp(addr) => Server::http(addr.as_str()),
+        #[cfg(unix)]
+        Listen::Unix(path) => {
+            remove_stale_socket(path)?;
+            Server::http_unix(path)
+        }
+    }
+    .map_err(|e| anyhow::anyhow!("failed to listen on {}: {}", options.listen, e))?;
+
+    // Resolved once, for requested paths to be compared with after they are
+    // resolved in turn.
+    let roots = options
+        .roots
+        .iter()
+        .map(|root| {
+            root.canonicalize()
+                .map_err(|e| anyhow::anyhow!("invalid root {}: {}", root.display(), e))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let roots = Arc::new(roots);
+
+    let stats = Arc::new(Stats::default());
+    let (sender, receiver) = mpsc::sync_channel::<Request>(options.queue_size);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let workers = options.workers.max(1);
+    for index in 0..workers {
+        let (engine, stats, receiver) = (engine.clone(), stats.clone(), receiver.clone());
+        let roots = roots.clone();
+        thread::Builder::new()
+            .name(format!("ocr-worker-{index}"))
+            .spawn(move || run_worker(&engine, &roots, &stats, &receiver))?;
+    }
+    println!("Listening on {} with {} workers", options.listen, workers);
+
+    for request in server.incoming_requests() {
+        let method = request.method().clone();
+        let route = route(request.url()).to_string();
+        match (method, route.as_str()) {
+            (Method::Get, "/health") => {
+                let health = serde_json::json!({
+                    "status": "ok",
+                    "model_version": crate::MODEL_VERSION,
+                    "workers": workers,
+                    "queued": stats.queued.load(Ordering::Relaxed),
+                    "running": stats.running.load(Ordering::Relaxed),
+                    "served": stats.served.load(Ordering::Relaxed),
+                    "failed": stats.failed.load(Ordering::Relaxed),
+                });
+                respond(
+                    request,
+                    200,
+                    "application/json",
+                    health.to_string().into_bytes(),
+                );
+            }
+            (Method::Post, "/ocr" | "/ocr/page") => enqueue(&sender, &stats, request),
+            (_, "/health" | "/ocr" | "/ocr/page") => {
+                respond_error(request, HttpError::new(405, "method not allowed"));
+            }
+            _ => respond_error(request, HttpError::new(404, "not found")),
+        }
+    }
+    Ok(())
+}
+
+/// Queues `request` for a worker, turning it down when the queue is full.
+fn enqueue(sender: &SyncSender<Request>, stats: &Stats, request: Request) {
+    stats.queued.fetch_add(1, Ordering::Relaxed);
+    if let Err(TrySendError::Full(request) | TrySendError::Disconnected(request)) =
+        sender.try_send(request)
+    {
+        stats.queued.fetch_sub(1, Ordering::Relaxed);
+        respond_error(request, HttpError::new(503, "queue full, retry later"));
+    }
+}
+
+fn run_worker(
+    engine: &OcrEngine,
+    roots: &[PathBuf],
+    stats: &Stats,
+    receiver: &Mutex<Receiver<Request>>,
+) {
+    loop {
+        // Only one idle worker waits on the queue at a time.
+        let Ok(mut request) = receiver.lock().unwrap().recv() else {
+            return;
+        };
+        stats.queued.fetch_sub(1, Ordering::Relaxed);
+        stats.running.fetch_add(1, Ordering::Relaxed);
+        let result = handle(engine, roots, &mut request);
+        stats.running.fetch_sub(1, Ordering::Relaxed);
+
+        match result {
+            Ok((content_type, body)) => {
+                stats.served.fetch_add(1, Ordering::Relaxed);
+                respond(request, 200, content_type, body);
+            }
+            Err(e) => {
+                stats.failed.fetch_add(1, Ordering::Relaxed);
+                respond_error(request, e);
+            }
+        }
+    }
+}
+
+fn handle(
+    engine: &OcrEngine,
+    roots: &[PathBuf],
+    request: &mut Request,
+) -> Result<(&'static str, Vec<u8>), HttpError> {
+    let format = match query_param(request.url(), "format") {
+        Some(format) => format
+            .parse::<OutputFormat>()
+            .map_err(|e| HttpError::new(400, e))?,
+        None => OutputFormat::Json,
+    };
+    let is_page = route(request.url()) == "/ocr/page";
+
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_SIZE + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| HttpError::new(400, format!("failed to read the body: {e}")))?;
+    if body.len() as u64 > MAX_BODY_SIZE {
+        return Err(HttpError::new(413, "body too large"));
+    }
+
+    let (name, image) = if is_page {
+        let page: PageRequest = serde_json::from_slice(&body)
+            .map_err(|e| HttpError::new(400, format!("invalid request: {e}")))?;
+        read_page(&page, roots)?
+    } else {
+        let image = decode(&body, "the image")?;
+        ("image".to_string(), image)
+    };
+
+    let (width, height) = (image.width(), image.height());
+    let blocks = engine.recognize(image, &CancellationToken::new())?;
+    let page = PageResult {
+        name,
+        width,
+        height,
+        blocks,
+    };
+
+    let mut out = Vec::new();
+    format
+        .writer()
+        .write(std::slice::from_ref(&page), &mut out)
+        .map_err(anyhow::Error::from)?;
+    Ok((content_type(format), out))
+}
+
+fn read_page(
+    request: &PageRequest,
+    roots: &[PathBuf],
+) -> Result<(String, DynamicImage), HttpError> {
+    let path = allowed_path(&request.path, roots)?;
+    let mut source = cbz::open_source(&path).map_err(|e| HttpError::new(400, e))?;
+    let name = match &request.page {
+        PageRef::Name(name) => name.clone(),
+        PageRef::Index(index) => source
+            .pages()
+            .get(*index)
+            .cloned()
+            .ok_or_else(|| HttpError::new(404, format!("no page {index}")))?,
+    };
+    let entry = source.read_page(&name).map_err(|e| match e {
+        cbz::CbzError::NotFound(_) => HttpError::new(404, e),
+        e => HttpError::new(500, e),
+    })?;
+    let image = decode(&entry.data, &name)?;
+    Ok((name, image))
+}
+
+/// Resolves `path`, failing unless it is inside one of `roots`, resolved
+/// already, so that neither `..` nor links lead out of them.
+fn allowed_path(path: &Path, roots: &[PathBuf]) -> Result<PathBuf, HttpError> {
+    if roots.is_empty() {
+        return Err(HttpError::new(
+            403,
+            "reading archives is disabled, start the server with --root",
+        ));
+    }
+    let outside = || {
+        HttpError::new(
+            403,
+            format!("{} is outside the server's roots", path.display()),
+        )
+    };
+    // Files outside the roots are refused the same whether they exist or not.
+    let resolved = path.canonicalize().map_err(|e| {
+        let inside = roots.iter().any(|root| path.starts_with(root))
+            && !path.components().any(|c| c == Component::ParentDir);
+        if inside {
+            HttpError::new(404, format!("{}: {e}", path.display()))
+        } else {
+            outside()
+        }
+    })?;
+    if !roots.iter().any(|root| resolved.starts_with(root)) {
+        return Err(outside());
+    }
+    Ok(resolved)
+}
+
+fn decode(data: &[u8], what: &str) -> Result<DynamicImage, HttpError> {
+    image::load_from_memory(data).map_err(|e| {
+        let status = match e {
+            image::ImageError::Unsupported(_) => 415,
+            _ => 422,
+        };
+        HttpError::new(status, format!("failed to decode {what}: {e}"))
+    })
+}
+
+/// Path of `url` without its query.
+fn route(url: &str) -> &str {
+    url.split_once('?').map_or(url, |(path, _)| path)
+}
+
+fn query_param<'a>(url: &'a str, key: &str) -> Option<&'a str> {
+    let (_, query) = url.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| value)
+}
+
+fn content_type(format: OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Json => "application/json",
+        OutputFormat::Hocr => "application/xhtml+xml",
+        OutputFormat::Alto => "application/xml",
+        OutputFormat::Text => "text/plain; charset=utf-8",
+    }
+}
+
+fn respond(request: Request, status: u16, content_type: &str, body: Vec<u8>) {
+    let header =
+        Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).expect("valid header");
+    let response = Response::from_data(body)
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        tracing::warn!("failed to send a response: {e}");
+    }
+}
+
+fn respond_error(request: Request, error: HttpError) {
+    let body = serde_json::json!({ "error": error.message }).to_string();
+    respond(request, error.status, "application/json", body.into_bytes());
+}
+
+/// Removes a socket left by a previous run, but nothing else.
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => Ok(std::fs::remove_file(path)?),
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    /// A root folder holding `page.cbz`, next to a folder outside of it
+    /// holding `secret.cbz`.
+    fn sandbox() -> (tempfile::TempDir, PathBuf, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let outside = dir.path().join("outside");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir(&outside).unwrap();
+        fs::write(root.join("page.cbz"), b"").unwrap();
+        fs::write(outside.join("secret.cbz"), b"").unwrap();
+        let root = root.canonicalize().unwrap();
+        let outside = outside.canonicalize().unwrap();
+        (dir, root, outside)
+    }
+
+    fn status(result: Result<PathBuf, HttpError>) -> u16 {
+        result.unwrap_err().status
+    }
+
+    #[test]
+    fn test_allowed_path() {
+        let (_dir, root, outside) = sandbox();
+        let roots = [root.clone()];
+
+        let page = root.join("page.cbz");
+        assert_eq!(allowed_path(&page, &roots).unwrap(), page);
+        assert_eq!(status(allowed_path(&page, &[])), 403);
+
+        assert_eq!(
+            status(allowed_path(&outside.join("secret.cbz"), &roots)),
+            403
+        );
+        assert_eq!(
+            status(allowed_path(&outside.join("missing.cbz"), &roots)),
+            403
+        );
+        assert_eq!(status(allowed_path(Path::new("/etc/passwd"), &roots)), 403);
+        assert_eq!(status(allowed_path(&root.join("missing.cbz"), &roots)), 404);
+    }
+
+    #[test]
+    fn test_allowed_path_parent_dir() {
+        let (_dir, root, _) = sandbox();
+        let roots = [root.clone()];
+
+        let inside = root.join("sub/../page.cbz");
+        assert_eq!(
+            allowed_path(&inside, &roots).unwrap(),
+            root.join("page.cbz")
+        );
+
+        let escape = root.join("../outside/secret.cbz");
+        assert_eq!(status(allowed_path(&escape, &roots)), 403);
+        let missing = root.join("sub/../../outside/missing.cbz");
+        assert_eq!(status(allowed_path(&missing, &roots)), 403);
+        // Whether a file exists outside the roots is not given away either.
+        let missing = root.join("sub/../missing.cbz");
+        assert_eq!(status(allowed_path(&missing, &roots)), 403);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_allowed_path_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let (_dir, root, outside) = sandbox();
+        let roots = [root.clone()];
+
+        symlink(outside.join("secret.cbz"), root.join("link.cbz")).unwrap();
+        assert_eq!(status(allowed_path(&root.join("link.cbz"), &roots)), 403);
+        symlink(&outside, root.join("linked")).unwrap();
+        let through = root.join("linked/secret.cbz");
+        assert_eq!(status(allowed_path(&through, &roots)), 403);
+
+        symlink(root.join("page.cbz"), root.join("sub/page.cbz")).unwrap();
+        let resolved = allowed_path(&root.join("sub/page.cbz"), &roots).unwrap();
+        assert_eq!(resolved, root.join("page.cbz"));
+    }
+
+    #[test]
+    fn test_query_param() {
+        let url = "/ocr/page?format=hocr&flag&page=2";
+        assert_eq!(route(url), "/ocr/page");
+        assert_eq!(query_param(url, "format"), Some("hocr"));
+        assert_eq!(query_param(url, "page"), Some("2"));
+        assert_eq!(query_param(url, "flag"), None);
+        assert_eq!(query_param(url, "form"), None);
+        assert_eq!(route("/ocr"), "/ocr");
+        assert_eq!(query_param("/ocr", "format"), None);
+        assert_eq!(query_param("/ocr?format=", "format"), Some(""));
+    }
+
+    /// Sends `raw` to `server` from another thread, returning the request
+    /// the server received and the thread reading its response.
+    fn send(server: &Server, raw: &'static str) -> (Request, thread::JoinHandle<String>) {
+        let addr = server.server_addr().to_ip().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(raw.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        (server.recv().unwrap(), client)
+    }
+
+    #[test]
+    fn test_queue_full() {
+        const REQUEST: &str = "POST /ocr HTTP/1.1\r\nHost: localhost\r\n\
+            Connection: close\r\nContent-Length: 0\r\n\r\n";
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let stats = Stats::default();
+        // Nothing takes requests off the queue.
+        let (sender, _receiver) = mpsc::sync_channel(1);
+
+        let (request, _) = send(&server, REQUEST);
+        enqueue(&sender, &stats, request);
+        assert_eq!(stats.queued.load(Ordering::Relaxed), 1);
+
+        let (request, client) = send(&server, REQUEST);
+        enqueue(&sender, &stats, request);
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+        assert!(response.ends_with(r#"{"error":"queue full, retry later"}"#));
+        assert_eq!(stats.queued.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,315 @@
+//! Debug rendering of what the models found on a page, to tune thresholds.
+
+use ab_glyph::{Font, FontArc, GlyphId, PxScale, ScaleFont};
+use image::{imageops, DynamicImage, GrayImage, Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_hollow_rect_mut};
+use imageproc::rect::Rect;
+
+use crate::output::PageResult;
+
+const LABEL_SIZE: f32 = 18.0;
+// Pixels per dot of the built-in glyphs, 3 by 5 dots.
+const BITMAP_SCALE: u32 = 3;
+const PANEL_MARGIN: u32 = 16;
+// Room for the recognized text right of each crop in the side panel.
+const PANEL_TEXT_WIDTH: u32 = 320;
+
+const BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+// Box colors by detector class.
+const PALETTE: [Rgba<u8>; 4] = [
+    Rgba([230, 25, 75, 255]),
+    Rgba([60, 180, 75, 255]),
+    Rgba([0, 130, 200, 255]),
+    Rgba([245, 130, 48, 255]),
+];
+
+#[derive(Default)]
+pub struct VisualizeOptions<'a> {
+    /// Font of the labels, which need one covering Japanese. Without it the
+    /// labels only show the index, confidence and class, drawn with built-in
+    /// glyphs.
+    pub font: Option<&'a FontArc>,
+    /// Drawn in red over the page, such as the map of
+    /// [`crate::comic_text_detector::ComicTextDetector::inference_with_score_map`].
+    pub mask: Option<&'a GrayImage>,
+    /// Shown in a panel right of the page, one per block in reading order,
+    /// such as [`crate::manga_ocr::MangaOcr::preprocess_view`] of each crop.
+    pub crops: &'a [DynamicImage],
+}
+
+/// Draws the boxes of `page` on `image`, each labeled with its reading-order
+/// index, confidence, class and text.
+pub fn render(image: &DynamicImage, page: &PageResult, options: &VisualizeOptions) -> RgbaImage {
+    let mut canvas = image.to_rgba8();
+
+    if let Some(mask) = options.mask {
+        let mask = if mask.dimensions() == canvas.dimensions() {
+            mask.clone()
+        } else {
+            imageops::resize(
+                mask,
+                canvas.width(),
+                canvas.height(),
+                imageops::FilterType::Triangle,
+            )
+        };
+        for (pixel, score) in canvas.pixels_mut().zip(mask.pixels()) {
+            blend(pixel, PALETTE[0], score[0] as f32 / 255.0 * 0.6);
+        }
+    }
+
+    let blocks = page.reading_order(true);
+    for (index, block) in blocks.iter().enumerate() {
+        let [xmin, ymin, xmax, ymax] = block.bbox.corners();
+        let color = PALETTE[block.bbox.data % PALETTE.len()];
+        let rect = Rect::at(xmin as i32, ymin as i32)
+            .of_size((xmax - xmin).max(1) as u32, (ymax - ymin).max(1) as u32);
+        draw_hollow_rect_mut(&mut canvas, rect, color);
+        if rect.width() > 2 && rect.height() > 2 {
+            let inner = Rect::at(rect.left() + 1, rect.top() + 1)
+                .of_size(rect.width() - 2, rect.height() - 2);
+            draw_hollow_rect_mut(&mut canvas, inner, color);
+        }
+
+        let header = format!(
+            "#{} {:.2} c{}",
+            index + 1,
+            block.bbox.confidence,
+            block.bbox.data
+        );
+        let (x, y) = (xmin as i32, ymin as i32);
+        match options.font {
+            Some(font) => {
+                let y = draw_label(&mut canvas, font, x, y, &header, color);
+                draw_label(&mut canvas, font, x, y, &block.text, color);
+            }
+            None => {
+                draw_bitmap_label(&mut canvas, x, y, &header, color);
+            }
+        }
+    }
+
+    if options.crops.is_empty() {
+        return canvas;
+    }
+
+    // Side panel: each crop with its index and text.
+    let row_height = options
+        .crops
+        .iter()
+        .map(|crop| crop.height())
+        .max()
+        .unwrap_or(0)
+        + PANEL_MARGIN;
+    let crop_width = options
+        .crops
+        .iter()
+        .map(|crop| crop.width())
+        .max()
+        .unwrap_or(0);
+    let panel_width = PANEL_MARGIN * 3 + crop_width + PANEL_TEXT_WIDTH;
+    let panel_height = PANEL_MARGIN + row_height * options.crops.len() as u32;
+
+    let mut output = RgbaImage::from_pixel(
+        canvas.width() + panel_width,
+        canvas.height().max(panel_height),
+        BACKGROUND,
+    );
+    imageops::replace(&mut output, &canvas, 0, 0);
+
+    let left = canvas.width() + PANEL_MARGIN;
+    for (index, crop) in options.crops.iter().enumerate() {
+        let top = PANEL_MARGIN + row_height * index as u32;
+        imageops::replace(&mut output, &crop.to_rgba8(), left as i64, top as i64);
+
+        let x = (left + crop_width + PANEL_MARGIN) as i32;
+        let number = format!("#{}", index + 1);
+        match options.font {
+            Some(font) => {
+                let text = blocks.get(index).map_or("", |block| block.text.as_str());
+                let y = draw_label(&mut output, font, x, top as i32, &number, BACKGROUND);
+                draw_label(&mut output, font, x, y, text, BACKGROUND);
+            }
+            None => {
+                draw_bitmap_label(&mut output, x, top as i32, &number, BACKGROUND);
+            }
+        }
+    }
+
+    output
+}
+
+/// Draws `text` in white on a `background` strip whose top left corner is
+/// at `x`, `y`, and returns the y below it.
+fn draw_label(
+    canvas: &mut RgbaImage,
+    font: &FontArc,
+    x: i32,
+    y: i32,
+    text: &str,
+    background: Rgba<u8>,
+) -> i32 {
+    if text.is_empty() {
+        return y;
+    }
+    let scaled = font.as_scaled(PxScale::from(LABEL_SIZE));
+    let height = scaled.height().ceil() as u32;
+
+    let width = text_width(font, text).ceil() as u32 + 4;
+    draw_filled_rect_mut(canvas, Rect::at(x, y).of_size(width, height), background);
+
+    let baseline = y as f32 + scaled.ascent();
+    let mut caret = x as f32 + 2.0;
+    let mut previous: Option<GlyphId> = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(LABEL_SIZE, ab_glyph::point(caret, baseline));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i32 + gx as i32;
+            let py = bounds.min.y as i32 + gy as i32;
+            if px >= 0 && py >= 0 && (px as u32) < canvas.width() && (py as u32) < canvas.height() {
+                blend(canvas.get_pixel_mut(px as u32, py as u32), WHITE, coverage);
+            }
+        });
+    }
+
+    y + height as i32
+}
+
+/// Like [`draw_label`] with the built-in glyphs, which only cover the
+/// characters of the index and confidence: others are left blank.
+fn draw_bitmap_label(
+    canvas: &mut RgbaImage,
+    x: i32,
+    y: i32,
+    text: &str,
+    background: Rgba<u8>,
+) -> i32 {
+    // A dot of margin around the text, and one between glyphs.
+    let count = text.chars().count() as u32;
+    let width = (4 * count + 1) * BITMAP_SCALE;
+    let height = 7 * BITMAP_SCALE;
+    draw_filled_rect_mut(canvas, Rect::at(x, y).of_size(width, height), background);
+
+    for (index, c) in text.chars().enumerate() {
+        let left = x + ((1 + 4 * index as u32) * BITMAP_SCALE) as i32;
+        for (row, bits) in bitmap_glyph(c).into_iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                let dot = Rect::at(
+                    left + (column * BITMAP_SCALE) as i32,
+                    y + ((row as u32 + 1) * BITMAP_SCALE) as i32,
+                )
+                .of_size(BITMAP_SCALE, BITMAP_SCALE);
+                draw_filled_rect_mut(canvas, dot, WHITE);
+            }
+        }
+    }
+
+    y + height as i32
+}
+
+/// Rows of a 3 by 5 glyph, top first, the leftmost dot in the highest bit.
+fn bitmap_glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        'c' => [0b000, 0b000, 0b111, 0b100, 0b111],
+        _ => [0; 5],
+    }
+}
+
+fn text_width(font: &FontArc, text: &str) -> f32 {
+    let scaled = font.as_scaled(PxScale::from(LABEL_SIZE));
+    let mut width = 0.0;
+    let mut previous: Option<GlyphId> = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+fn blend(pixel: &mut Rgba<u8>, color: Rgba<u8>, alpha: f32) {
+    for channel in 0..3 {
+        let mixed = pixel[channel] as f32 * (1.0 - alpha) + color[channel] as f32 * alpha;
+        pixel[channel] = mixed.round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comic_text_detector::Bbox;
+    use crate::pipeline::TextBlock;
+
+    #[test]
+    fn test_render_without_font() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(200, 120, WHITE));
+        let page = PageResult {
+            name: "001.jpg".to_string(),
+            width: 200,
+            height: 120,
+            blocks: vec![TextBlock {
+                bbox: Bbox {
+                    xmin: 10,
+                    xmax: 60,
+                    ymin: 20,
+                    ymax: 100,
+                    confidence: 0.91,
+                    data: 1,
+                },
+                text: "テキスト".to_string(),
+            }],
+        };
+        let canvas = render(&image, &page, &VisualizeOptions::default());
+        assert_eq!(canvas.dimensions(), (200, 120));
+
+        // A box two pixels thick in the color of the class, below the label.
+        let color = PALETTE[1];
+        for (x, y) in [(10, 60), (11, 60), (59, 60), (58, 60), (30, 99), (30, 98)] {
+            assert_eq!(*canvas.get_pixel(x, y), color, "({x}, {y})");
+        }
+        for (x, y) in [(12, 60), (57, 60), (30, 97), (61, 60), (9, 60), (30, 101)] {
+            assert_eq!(*canvas.get_pixel(x, y), WHITE, "({x}, {y})");
+        }
+
+        // The label "#1 0.91 c1" at the top left corner of the box: white
+        // dots on the color of the class.
+        let label = "#1 0.91 c1";
+        let (width, height) = ((4 * label.len() as u32 + 1) * 3, 21);
+        assert_eq!(*canvas.get_pixel(10, 20), color);
+        assert_eq!(*canvas.get_pixel(10 + width - 1, 20 + height - 1), color);
+        assert_eq!(*canvas.get_pixel(10 + width, 30), WHITE);
+        // The top left dot of "#" and the gap in the middle of its top row.
+        assert_eq!(*canvas.get_pixel(13, 23), WHITE);
+        assert_eq!(*canvas.get_pixel(16, 23), color);
+    }
+}
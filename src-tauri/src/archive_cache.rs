@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cbz::{CbzArchive, CbzError, ComicSource, OcrSidecar, SourceKind};
+
+/// Default memory budget for archives kept in RAM.
+pub const DEFAULT_BUDGET_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Archives bigger than this are never read into memory, whatever the budget.
+const FILE_BACKED_THRESHOLD_BYTES: u64 = 256 * 1024 * 1024;
+
+pub type CachedArchive = Box<dyn ComicSource + Send>;
+
+/// Error returned by [`ArchiveCache::load`] for an encrypted archive opened
+/// without a password, so the frontend can ask for one.
+pub const PASSWORD_REQUIRED: &str = "password-required";
+
+/// Error returned by [`ArchiveCache::load`] when the password does not
+/// decrypt the archive.
+pub const WRONG_PASSWORD: &str = "wrong-password";
+
+/// Identifies the version of a file on disk, to notice it was replaced.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    fn of(path: &Path) -> std::io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn tag(&self) -> String {
+        let modified = self
+            .modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos());
+        format!("{:x}-{:x}", self.len, modified)
+    }
+}
+
+struct Entry {
+    archive: CachedArchive,
+    fingerprint: Fingerprint,
+    // Bytes of the file held in RAM, zero for archives read from disk.
+    resident: u64,
+    // Kept to reopen the archive when it changes on disk.
+    password: Option<String>,
+    ocr: Option<OcrSidecar>,
+    lost: Vec<LostEntry>,
+    last_used: u64,
+}
+
+impl Entry {
+    fn memory(&self) -> u64 {
+        self.resident + self.archive.cached_size()
+    }
+}
+
+/// Opened archives keyed by path, evicted least recently used first once what
+/// they hold in memory goes over the budget. Archives read from disk only drop
+/// what they read on demand, such as decompressed nested archives.
+pub struct ArchiveCache {
+    entries: HashMap<String, Entry>,
+    budget: u64,
+    clock: u64,
+    // Read by the background OCR job, so never evicted.
+    pinned: Option<String>,
+}
+
+impl ArchiveCache {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            budget,
+            clock: 0,
+            pinned: None,
+        }
+    }
+
+    /// Opens `path`, which may be any [`SourceKind`].
+    ///
+    /// Zip archives are read into memory when they fit the budget and from
+    /// disk otherwise. 7z archives are always decompressed into memory, tar
+    /// archives, EPUBs and folders are always read from disk.
+    ///
+    /// Encrypted zip archives need `password`, failing with
+    /// [`PASSWORD_REQUIRED`] or [`WRONG_PASSWORD`] otherwise.
+    ///
+    /// Does not touch the cache, so it can run without holding its lock.
+    pub fn load(
+        path: &str,
+        budget: u64,
+        password: Option<String>,
+    ) -> Result<LoadedArchive, String> {
+        let fingerprint =
+            Fingerprint::of(Path::new(path)).map_err(|e| format!("Failed to read file: {}", e))?;
+        let kind = SourceKind::of(Path::new(path));
+        let parse_error = |e: cbz::CbzError| format!("Failed to parse {:?} comic: {}", kind, e);
+
+        let fits = fingerprint.len <= budget && fingerprint.len <= FILE_BACKED_THRESHOLD_BYTES;
+        let opened: cbz::Result<(CachedArchive, u64)> = match kind {
+            SourceKind::Zip if fits => {
+                let data = fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+                println!("[Rust] Read {} bytes", data.len());
+                CbzArchive::from_bytes(data)
+                    .and_then(|archive| unlock(archive, path, password.as_deref()))
+                    .map(|archive| (Box::new(archive) as CachedArchive, fingerprint.len))
+            }
+            SourceKind::Zip => {
+                println!("[Rust] Opening {} from disk", path);
+                CbzArchive::open(path)
+                    .and_then(|archive| unlock(archive, path, password.as_deref()))
+                    .map(|archive| (Box::new(archive) as CachedArchive, 0))
+            }
+            // Decompressed when opened, which `cached_size` counts.
+            SourceKind::SevenZip => cbz::open_source(path).map(|source| (source, 0)),
+            _ => {
+                println!("[Rust] Opening {} from disk", path);
+                cbz::open_source(path).map(|source| (source, 0))
+            }
+        };
+        let mut lost = Vec::new();
+        let (mut archive, resident) = match opened {
+            Ok(opened) => opened,
+            Err(CbzError::PasswordRequired(_)) => return Err(PASSWORD_REQUIRED.to_string()),
+            Err(CbzError::WrongPassword(_)) => return Err(WRONG_PASSWORD.to_string()),
+            Err(e) if kind == SourceKind::Zip && e.is_damaged_archive() => {
+                println!("[Rust] {}, trying to recover", parse_error(e));
+                let (archive, recovery_lost) = Self::recover(path)?;
+                lost = recovery_lost;
+                (Box::new(archive) as CachedArchive, fingerprint.len)
+            }
+            Err(e) => return Err(parse_error(e)),
+        };
+
+        let ocr = archive.ocr_sidecar().unwrap_or_else(|e| {
+            println!("[Rust] Ignoring embedded OCR results: {}", e);
+            None
+        });
+
+        Ok(LoadedArchive {
+            archive,
+            fingerprint,
+            resident,
+            password,
+            ocr,
+            lost,
+        })
+    }
+
+    /// Salvages the intact entries of a damaged zip, such as a partial
+    /// download, returning the ones that are lost.
+    fn recover(path: &str) -> Result<(CbzArchive<Cursor<Vec<u8>>>, Vec<LostEntry>), String> {
+        let data = fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+        let recovery =
+            CbzArchive::recover(&data).map_err(|e| format!("Failed to parse CBZ: {}", e))?;
+        println!(
+            "[Rust] Recovered {} entries, lost {}",
+            recovery.archive.len(),
+            recovery.lost.len()
+        );
+        let lost = recovery
+            .lost
+            .iter()
+            .map(|lost| {
+                println!("[Rust] Lost {}: {}", lost.name, lost.error);
+                LostEntry {
+                    name: lost.name.clone(),
+                    error: lost.error.to_string(),
+                }
+            })
+            .collect();
+        if recovery.archive.is_empty() {
+            return Err("Failed to parse CBZ: no entry could be recovered".to_string());
+        }
+        Ok((recovery.archive, lost))
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, budget: u64) {
+        self.budget = budget;
+        self.evict(None);
+    }
+
+    pub fn insert(&mut self, path: String, loaded: LoadedArchive) -> &mut CachedArchive {
+        self.clock += 1;
+        self.entries.insert(
+            path.clone(),
+            Entry {
+                archive: loaded.archive,
+                fingerprint: loaded.fingerprint,
+                resident: loaded.resident,
+                password: loaded.password,
+                ocr: loaded.ocr,
+                lost: loaded.lost,
+                last_used: self.clock,
+            },
+        );
+        self.evict(Some(&path));
+        &mut self.entries.get_mut(&path).unwrap().archive
+    }
+
+    /// Keeps the archive at `path` open whatever the budget, for the
+    /// background OCR job reading it.
+    pub fn pin(&mut self, path: &str) {
+        self.pinned = Some(path.to_string());
+    }
+
+    /// Returns the archive opened from `path`, as it was then: see
+    /// [`reload_if_changed`] to pick up changes on disk.
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut CachedArchive> {
+        // Make room for what the last read of an archive brought into memory.
+        self.evict(Some(path));
+        self.clock += 1;
+        let entry = self.entries.get_mut(path)?;
+        entry.last_used = self.clock;
+        Some(&mut entry.archive)
+    }
+
+    /// Identifies the version of the file the archive at `path` was opened
+    /// from, which changes when it is reopened after changing on disk.
+    pub fn version(&self, path: &str) -> String {
+        self.entries
+            .get(path)
+            .map(|entry| entry.fingerprint.tag())
+            .unwrap_or_default()
+    }
+
+    /// OCR results embedded in the archive opened from `path`.
+    pub fn ocr_sidecar(&self, path: &str) -> Option<&OcrSidecar> {
+        self.entries.get(path)?.ocr.as_ref()
+    }
+
+    /// Entries that could not be salvaged when the archive at `path` was
+    /// opened damaged, empty for an intact archive.
+    pub fn lost(&self, path: &str) -> Vec<LostEntry> {
+        self.entries
+            .get(path)
+            .map(|entry| entry.lost.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn remove(&mut self, path: &str) {
+        self.entries.remove(path);
+    }
+
+    fn evict(&mut self, keep: Option<&str>) {
+        loop {
+            let memory: u64 = self.entries.values().map(Entry::memory).sum();
+            if memory <= self.budget {
+                return;
+            }
+            let oldest = self
+                .entries
+                .iter()
+                .filter(|(path, entry)| {
+                    entry.memory() > 0
+                        && Some(path.as_str()) != keep
+                        && Some(path.as_str()) != self.pinned.as_deref()
+                })
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+            let Some(oldest) = oldest else {
+                return;
+            };
+            let entry = self.entries.get_mut(&oldest).unwrap();
+            if entry.resident == 0 {
+                println!(
+                    "[Rust] Clearing what {} read into the archive cache",
+                    oldest
+                );
+                entry.archive.clear_cache();
+            }
+            // 7z archives keep their decompressed entries until closed.
+            if entry.memory() > 0 {
+                println!("[Rust] Evicting {} from the archive cache", oldest);
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Reopens the archive at `path` if its file changed on disk since it was
+/// opened.
+///
+/// The new version is loaded without holding `cache`'s lock, so other
+/// commands are not held up by a large archive, and swapped in once loaded.
+/// When it fails to load, the version already open is kept.
+pub fn reload_if_changed(cache: &Mutex<ArchiveCache>, path: &str) {
+    let (fingerprint, password, budget) = {
+        let archives = cache.lock().unwrap();
+        let Some(entry) = archives.entries.get(path) else {
+            return;
+        };
+        (entry.fingerprint, entry.password.clone(), archives.budget)
+    };
+    if Fingerprint::of(Path::new(path)).ok() == Some(fingerprint) {
+        return;
+    }
+
+    println!("[Rust] {} changed on disk, reopening", path);
+    match ArchiveCache::load(path, budget, password) {
+        Ok(loaded) => {
+            let mut archives = cache.lock().unwrap();
+            // Unless it was closed or reloaded by another caller meanwhile.
+            let unchanged = archives
+                .entries
+                .get(path)
+                .is_some_and(|entry| entry.fingerprint == fingerprint);
+            if unchanged {
+                archives.insert(path.to_string(), loaded);
+            }
+        }
+        Err(e) => println!("[Rust] Keeping the open version of {}: {}", path, e),
+    }
+}
+
+/// Runs `replace`, which rewrites the file of the archive open at `path`,
+/// with the archive closed, since Windows cannot replace a file that is open.
+///
+/// The archive is reopened from the new file once `replace` succeeds, and
+/// put back as it was when it fails.
+pub fn replace_file<T>(
+    cache: &Mutex<ArchiveCache>,
+    path: &str,
+    replace: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let (entry, budget) = {
+        let mut archives = cache.lock().unwrap();
+        let entry = archives.entries.remove(path).ok_or("Archive not opened")?;
+        (entry, archives.budget)
+    };
+
+    let replaced = replace();
+    let loaded = match &replaced {
+        Ok(_) => ArchiveCache::load(path, budget, entry.password.clone()),
+        Err(_) => Ok(LoadedArchive::from(entry)),
+    };
+    match loaded {
+        Ok(loaded) => {
+            let mut archives = cache.lock().unwrap();
+            // Unless it was opened again by another caller meanwhile.
+            if !archives.entries.contains_key(path) {
+                archives.insert(path.to_string(), loaded);
+            }
+        }
+        Err(e) => println!("[Rust] Failed to reopen {}: {}", path, e),
+    }
+    replaced
+}
+
+pub struct LoadedArchive {
+    archive: CachedArchive,
+    fingerprint: Fingerprint,
+    resident: u64,
+    password: Option<String>,
+    ocr: Option<OcrSidecar>,
+    lost: Vec<LostEntry>,
+}
+
+impl From<Entry> for LoadedArchive {
+    fn from(entry: Entry) -> Self {
+        Self {
+            archive: entry.archive,
+            fingerprint: entry.fingerprint,
+            resident: entry.resident,
+            password: entry.password,
+            ocr: entry.ocr,
+            lost: entry.lost,
+        }
+    }
+}
+
+/// An entry of a damaged archive left out when it was recovered.
+#[derive(Clone, serde::Serialize)]
+pub struct LostEntry {
+    pub name: String,
+    pub error: String,
+}
+
+/// Checks `password` against an encrypted archive, and that one was given.
+fn unlock<R: std::io::Read + std::io::Seek>(
+    mut archive: CbzArchive<R>,
+    path: &str,
+    password: Option<&str>,
+) -> cbz::Result<CbzArchive<R>> {
+    if archive.is_encrypted() {
+        match password {
+            Some(password) => archive.set_password(password)?,
+            None => return Err(CbzError::PasswordRequired(path.to_string())),
+        }
+    }
+    Ok(archive)
+}
@@ -1,6 +1,6 @@
 use magnum::container::ogg::OpusSourceOgg;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::Mutex;
 use tauri::{
     Emitter, Manager, State,
     menu::{Menu, MenuItemBuilder, PredefinedMenuItem, Submenu},
@@ -8,16 +8,29 @@ use tauri::{
 
 use rusqlite::{Connection, named_params};
 use std::fs::File;
-use std::io::Cursor;
 use std::num::ParseIntError;
 use std::{fs, io::Read, path::PathBuf};
 
-use cbz::CbzArchive;
+use cbz::{Chapter, ComicInfo};
+use comic_ocr::engine::{EngineOptions, OcrEngine};
+use comic_ocr::pipeline::CancellationToken;
+
+mod archive_cache;
+mod mokuro;
+mod ocr_jobs;
+mod page_image;
+mod protocol;
+
+use archive_cache::{ArchiveCache, LostEntry};
+use ocr_jobs::{OcrJobs, OcrProgress};
 
 struct AppState {
-    archives: Mutex<HashMap<String, CbzArchive<Cursor<Vec<u8>>>>>,
-    ocr: Arc<Mutex<Option<comic_ocr::manga_ocr::MangaOcr>>>,
-    detector: Arc<Mutex<Option<comic_ocr::comic_text_detector::ComicTextDetector>>>,
+    archives: Mutex<ArchiveCache>,
+    // Set once by `init_ocr`, never unset.
+    engine: tokio::sync::OnceCell<OcrEngine>,
+    jobs: OcrJobs,
+    // In-flight `get_page_with_ocr` calls by frontend request id.
+    requests: Mutex<HashMap<u64, CancellationToken>>,
 }
 
 const MENU_EVENT_LOOKUP: &str = "lookup";
@@ -191,38 +204,56 @@ fn get_content(handle: tauri::AppHandle) -> String {
 }
 
 #[tauri::command]
-async fn open_cbz(state: State<'_, AppState>, path: String) -> Result<Vec<String>, String> {
+async fn open_cbz(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    password: Option<String>,
+) -> Result<Vec<String>, String> {
     let id = path.clone();
-    {
-        let archives = state.archives.lock().unwrap();
+    reload_if_changed(&app, &id).await;
+    let cached = {
+        let mut archives = state.archives.lock().unwrap();
 
         // check cache.
-        if archives.contains_key(&id) {
-            let archive = archives.get(&id).ok_or("Archive not opened")?;
-            let pages = archive.image_names();
-            return Ok(pages);
-        }
+        let pages = archives.get_mut(&id).map(|archive| archive.pages());
+        pages.map(|pages| {
+            let to_ocr = pages_without_ocr(&archives, &id, &pages);
+            (pages, to_ocr)
+        })
+    };
+    if let Some((pages, to_ocr)) = cached {
+        state.archives.lock().unwrap().pin(&id);
+        state.jobs.start(app, id, to_ocr);
+        return Ok(pages);
     }
 
     println!("[Rust] open_cbz called with path: {}", path);
-    let data = tokio::fs::read(&path).await.map_err(|e| {
-        let err = format!("Failed to read file: {}", e);
-        println!("[Rust] Error: {}", err);
-        err
-    })?;
-    println!("[Rust] Read {} bytes", data.len());
-
-    let archive = CbzArchive::from_bytes(data).map_err(|e| {
-        let err = format!("Failed to parse CBZ: {}", e);
-        println!("[Rust] Error: {}", err);
-        err
-    })?;
-    let pages = archive.image_names();
+    let budget = state.archives.lock().unwrap().budget();
+    let loaded = tokio::task::spawn_blocking(move || ArchiveCache::load(&path, budget, password))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|err| {
+            println!("[Rust] Error: {}", err);
+            err
+        })?;
+
+    let (pages, to_ocr) = {
+        let mut archives = state.archives.lock().unwrap();
+        archives.pin(&id);
+        let pages = archives.insert(id.clone(), loaded).pages();
+        let to_ocr = pages_without_ocr(&archives, &id, &pages);
+        (pages, to_ocr)
+    };
     println!("[Rust] Found {} pages", pages.len());
+    if to_ocr.len() < pages.len() {
+        println!(
+            "[Rust] {} pages have embedded OCR results",
+            pages.len() - to_ocr.len()
+        );
+    }
 
-    //let mut archives = state.archives.lock().unwrap();
-    let mut archives = state.archives.lock().unwrap();
-    archives.insert(id, archive);
+    state.jobs.start(app, id, to_ocr);
 
     Ok(pages)
 }
@@ -234,161 +265,324 @@ fn close_cbz(state: State<'_, AppState>, path: String) -> Result<(), String> {
     Ok(())
 }
 
+/// Whether `path` is a directory, which opens as a folder of images.
+#[tauri::command]
+fn is_directory(path: String) -> Result<bool, String> {
+    std::fs::metadata(&path)
+        .map(|metadata| metadata.is_dir())
+        .map_err(|e| e.to_string())
+}
+
+/// Returns the archive's `ComicInfo.xml`, used for the title and reading direction.
+#[tauri::command]
+fn get_metadata(state: State<'_, AppState>, path: String) -> Result<Option<ComicInfo>, String> {
+    let mut archives = state.archives.lock().unwrap();
+    let archive = archives.get_mut(&path).ok_or("Archive not opened")?;
+    archive.metadata().map_err(|e| e.to_string())
+}
+
+/// Returns the entries left out of a damaged archive that was recovered.
+#[tauri::command]
+fn get_lost_entries(state: State<'_, AppState>, path: String) -> Vec<LostEntry> {
+    state.archives.lock().unwrap().lost(&path)
+}
+
+/// Returns the chapters of the archive, from subdirectories or nested archives.
+#[tauri::command]
+fn get_chapters(state: State<'_, AppState>, path: String) -> Result<Vec<Chapter>, String> {
+    let mut archives = state.archives.lock().unwrap();
+    let archive = archives.get_mut(&path).ok_or("Archive not opened")?;
+    Ok(archive.chapters())
+}
+
+/// Sets how many MiB of archives are kept in memory before evicting.
+#[tauri::command]
+fn set_archive_cache_budget(state: State<'_, AppState>, megabytes: u64) {
+    let mut archives = state.archives.lock().unwrap();
+    archives.set_budget(megabytes * 1024 * 1024);
+}
+
+#[tauri::command]
+fn pause_ocr_job(state: State<'_, AppState>) -> OcrProgress {
+    state.jobs.pause();
+    state.jobs.progress()
+}
+
+#[tauri::command]
+fn resume_ocr_job(state: State<'_, AppState>) -> OcrProgress {
+    state.jobs.resume();
+    state.jobs.progress()
+}
+
+#[tauri::command]
+fn cancel_ocr_job(state: State<'_, AppState>) -> OcrProgress {
+    state.jobs.cancel();
+    state.jobs.progress()
+}
+
+#[tauri::command]
+fn get_ocr_job_progress(state: State<'_, AppState>) -> OcrProgress {
+    state.jobs.progress()
+}
+
 #[derive(serde::Serialize)]
 struct PageResult {
-    image: String,
+    url: String,
     mime_type: String,
     width: u32,
     height: u32,
 }
 
+/// Reopens the archive at `path` if it changed on disk, off the async
+/// runtime since that reads the whole file.
+async fn reload_if_changed(app: &tauri::AppHandle, path: &str) {
+    let (app, path) = (app.clone(), path.to_string());
+    let reloaded = tokio::task::spawn_blocking(move || {
+        archive_cache::reload_if_changed(&app.state::<AppState>().archives, &path)
+    })
+    .await;
+    if let Err(e) = reloaded {
+        println!("[Rust] Failed to check for changes: {}", e);
+    }
+}
+
 #[tauri::command]
-fn get_page(
+async fn get_page(
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
     path: String,
     page_name: String,
 ) -> Result<PageResult, String> {
     println!("[Rust] get_page called: {} / {}", path, page_name);
+    reload_if_changed(&app, &path).await;
     let mut archives = state.archives.lock().unwrap();
     let archive = archives.get_mut(&path).ok_or("Archive not opened")?;
 
-    let image = archive.read_image(&page_name).map_err(|e| e.to_string())?;
-    let mime_type = image.mime_type().to_string();
+    // The webview loads the bytes from `url`, only the size is needed here.
+    let page = page_image::probe(archive, &page_name)?;
+    let mime_type = page_image::served_mime_type(page.format).to_string();
     println!(
-        "[Rust] Image mime_type: {}, size: {} bytes",
-        mime_type,
-        image.data.len()
+        "[Rust] Image mime_type: {}, dimensions: {}x{}",
+        mime_type, page.width, page.height
     );
 
-    let img = image::load_from_memory(&image.data).map_err(|e| e.to_string())?;
-    let (width, height) = (img.width(), img.height());
-    println!("[Rust] Image dimensions: {}x{}", width, height);
-
-    let encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &image.data);
-
     Ok(PageResult {
-        image: encoded,
+        url: protocol::page_url(&path, &page_name, &archives.version(&path)),
         mime_type,
-        width,
-        height,
+        width: page.width,
+        height: page.height,
     })
 }
 
-#[derive(serde::Serialize)]
+#[derive(Clone, serde::Serialize)]
 struct OcrResult {
     text: String,
-    bbox: (usize, usize, usize, usize),
+    /// `[xmin, ymin, xmax, ymax]` in pixels of the page.
+    bbox: [usize; 4],
     confidence: f32,
 }
 
 #[derive(serde::Serialize)]
 struct PageWithOcrResult {
-    image: String,
+    url: String,
     mime_type: String,
     width: u32,
     height: u32,
     ocr_results: Vec<OcrResult>,
 }
 
+/// OCR results for `page_name` embedded in the archive, if they were produced
+/// by the models yonde runs.
+///
+/// Results imported from Mokuro count, as it runs the same detector and
+/// manga-ocr models.
+fn embedded_ocr(archives: &ArchiveCache, path: &str, page_name: &str) -> Option<Vec<OcrResult>> {
+    let sidecar = archives.ocr_sidecar(path)?;
+    if sidecar.model_version != comic_ocr::MODEL_VERSION && !cbz::mokuro::is_from_mokuro(sidecar) {
+        return None;
+    }
+    let page = sidecar.pages.get(page_name)?;
+    let results = page
+        .blocks
+        .iter()
+        .map(|block| OcrResult {
+            text: block.text.clone(),
+            bbox: block.bbox.map(|v| v as usize),
+            confidence: block.confidence,
+        })
+        .collect();
+    Some(results)
+}
+
+/// Pages of `pages` the background job still has to OCR.
+fn pages_without_ocr(archives: &ArchiveCache, path: &str, pages: &[String]) -> Vec<String> {
+    pages
+        .iter()
+        .filter(|page| embedded_ocr(archives, path, page).is_none())
+        .cloned()
+        .collect()
+}
+
+/// Detects text regions on `img` and recognizes each of them, blocking
+/// until one of the engine's workers is done with it.
+///
+/// Returns `None` when `cancel` fired before the page was done.
+fn ocr_image(
+    engine: &OcrEngine,
+    img: image::DynamicImage,
+    cancel: &CancellationToken,
+) -> Option<Vec<OcrResult>> {
+    println!("[Rust] Running text detection and recognition...");
+    let blocks = match engine.recognize(img, cancel) {
+        Ok(blocks) => blocks,
+        Err(e) if e.is::<comic_ocr::pipeline::Cancelled>() => {
+            println!("[Rust] OCR cancelled");
+            return None;
+        }
+        Err(e) => {
+            println!("[Rust] OCR error: {}", e);
+            return Some(Vec::new());
+        }
+    };
+    println!("[Rust] Found {} text regions", blocks.len());
+
+    let results = blocks
+        .into_iter()
+        .filter(|block| !block.text.is_empty())
+        .map(|block| {
+            let bbox = block.bbox;
+            println!(
+                "[Rust] OCR text: {} (confidence: {:.2})",
+                block.text, bbox.confidence
+            );
+            OcrResult {
+                text: block.text,
+                bbox: bbox.corners(),
+                confidence: bbox.confidence,
+            }
+        })
+        .collect();
+    Some(results)
+}
+
 #[tauri::command]
 async fn get_page_with_ocr(
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
     path: String,
     page_name: String,
+    request_id: Option<u64>,
 ) -> Result<PageWithOcrResult, String> {
     println!("[Rust] get_page_with_ocr called: {} / {}", path, page_name);
-    let image_data = {
+    reload_if_changed(&app, &path).await;
+    let (page, embedded, url) = {
         let mut archives = state.archives.lock().unwrap();
         let archive = archives.get_mut(&path).ok_or("Archive not opened")?;
-        archive.read_image(&page_name).map_err(|e| e.to_string())?
+        let page = page_image::probe(archive, &page_name)?;
+        let url = protocol::page_url(&path, &page_name, &archives.version(&path));
+        (page, embedded_ocr(&archives, &path, &page_name), url)
     };
 
-    let mime_type = image_data.mime_type().to_string();
-    let img = image::load_from_memory(&image_data.data).map_err(|e| e.to_string())?;
-    let (width, height) = (img.width(), img.height());
+    let mime_type = page_image::served_mime_type(page.format).to_string();
+    let (width, height) = (page.width, page.height);
     println!("[Rust] Image: {}x{}", width, height);
 
-    let encoded =
-        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &image_data.data);
-
-    // Clone Arc references for the thread
-    let detector_arc = state.detector.clone();
-    let ocr_arc = state.ocr.clone();
-    let img_bytes = image_data.data.clone();
-
-    // Check if OCR is initialized
-    {
-        let det_guard = detector_arc.lock().unwrap();
-        let ocr_guard = ocr_arc.lock().unwrap();
-        if det_guard.is_none() || ocr_guard.is_none() {
-            println!("[Rust] OCR not initialized, returning empty results");
-            return Ok(PageWithOcrResult {
-                image: encoded,
-                mime_type,
-                width,
-                height,
-                ocr_results: Vec::new(),
-            });
+    // Shared volumes carry their results, no need for the models.
+    if let Some(ocr_results) = embedded {
+        println!(
+            "[Rust] Returning {} embedded OCR results",
+            ocr_results.len()
+        );
+        return Ok(PageWithOcrResult {
+            url,
+            mime_type,
+            width,
+            height,
+            ocr_results,
+        });
+    }
+
+    if !page_image::can_decode(page.format) {
+        println!("[Rust] {} pages cannot be OCR'd", page.format.mime_type());
+        return Ok(PageWithOcrResult {
+            url,
+            mime_type,
+            width,
+            height,
+            ocr_results: Vec::new(),
+        });
+    }
+
+    let Some(engine) = state.engine.get().cloned() else {
+        println!("[Rust] OCR not initialized, returning empty results");
+        return Ok(PageWithOcrResult {
+            url,
+            mime_type,
+            width,
+            height,
+            ocr_results: Vec::new(),
+        });
+    };
+
+    let cancel = CancellationToken::new();
+    if let Some(id) = request_id {
+        let mut requests = state.requests.lock().unwrap();
+        requests.insert(id, cancel.clone());
+    }
+
+    // The background job may already have this page, or be about to.
+    state.jobs.focus(&path, &page_name);
+    let job_results = state.jobs.wait_for(&path, &page_name, &cancel).await;
+    if cancel.is_cancelled() {
+        return Err("OCR request cancelled".to_string());
+    }
+    if let Some(ocr_results) = job_results {
+        if let Some(id) = request_id {
+            state.requests.lock().unwrap().remove(&id);
         }
+        println!(
+            "[Rust] Returning {} OCR results from job",
+            ocr_results.len()
+        );
+        return Ok(PageWithOcrResult {
+            url,
+            mime_type,
+            width,
+            height,
+            ocr_results,
+        });
     }
 
-    // Run OCR in a thread with larger stack
-    let ocr_results = std::thread::Builder::new()
-        .stack_size(4 * 1024 * 1024) // 4MB stack for inference
-        .spawn(move || {
-            let mut img = image::load_from_memory(&img_bytes).unwrap();
-
-            let det_guard = detector_arc.lock().unwrap();
-            let ocr_guard = ocr_arc.lock().unwrap();
-
-            if let (Some(detector), Some(ocr)) = (det_guard.as_ref(), ocr_guard.as_ref()) {
-                println!("[Rust] Running text detection...");
-                match detector.inference(&img) {
-                    Ok(bboxes) => {
-                        println!("[Rust] Found {} text regions", bboxes.len());
-                        let mut results = Vec::new();
-                        for bbox in bboxes {
-                            let crop = img.crop(
-                                bbox.xmin as u32,
-                                bbox.ymin as u32,
-                                (bbox.xmax - bbox.xmin) as u32,
-                                (bbox.ymax - bbox.ymin) as u32,
-                            );
-
-                            if let Ok(texts) = ocr.inference(&[crop]) {
-                                if let Some(text) = texts.first() {
-                                    if !text.is_empty() {
-                                        println!(
-                                            "[Rust] OCR text: {} (confidence: {:.2})",
-                                            text, bbox.confidence
-                                        );
-                                        results.push(OcrResult {
-                                            text: text.clone(),
-                                            bbox: (bbox.xmin, bbox.ymin, bbox.xmax, bbox.ymax),
-                                            confidence: bbox.confidence,
-                                        });
-                                    }
-                                }
-                            }
-                        }
-                        results
-                    }
-                    Err(e) => {
-                        println!("[Rust] Detection error: {}", e);
-                        Vec::new()
-                    }
-                }
-            } else {
-                Vec::new()
-            }
+    // Only read whole when OCR has to run here.
+    let image_data = {
+        let mut archives = state.archives.lock().unwrap();
+        archives
+            .get_mut(&path)
+            .ok_or_else(|| "Archive not opened".to_string())
+            .and_then(|archive| archive.read_page(&page_name).map_err(|e| e.to_string()))
+    };
+
+    // Inference runs on the engine's workers, only decoding and waiting
+    // happen here.
+    let ocr_results = match image_data {
+        Ok(image_data) => tokio::task::spawn_blocking(move || {
+            let img = page_image::decode(&image_data)?;
+            Ok(ocr_image(&engine, img, &cancel))
         })
-        .map_err(|e| format!("Failed to spawn thread: {}", e))?
-        .join()
-        .map_err(|_| "Thread panicked".to_string())?;
+        .await
+        .map_err(|_| "Thread panicked".to_string())
+        .and_then(|decoded| decoded),
+        Err(e) => Err(e),
+    };
+
+    if let Some(id) = request_id {
+        state.requests.lock().unwrap().remove(&id);
+    }
+    let ocr_results = ocr_results?.ok_or("OCR request cancelled")?;
 
     println!("[Rust] Returning {} OCR results", ocr_results.len());
     Ok(PageWithOcrResult {
-        image: encoded,
+        url,
         mime_type,
         width,
         height,
@@ -396,81 +590,64 @@ async fn get_page_with_ocr(
     })
 }
 
+#[tauri::command]
+fn cancel_ocr_request(state: State<'_, AppState>, request_id: u64) {
+    if let Some(cancel) = state.requests.lock().unwrap().remove(&request_id) {
+        println!("[Rust] Cancelling OCR request {}", request_id);
+        cancel.cancel();
+        state.jobs.wake_waiters();
+    }
+}
+
 #[tauri::command]
 async fn init_ocr(state: State<'_, AppState>) -> Result<(), String> {
     println!("[Rust] init_ocr called");
-    {
-        let detector = state.detector.lock().unwrap();
-        if detector.is_some() {
-            println!("[Rust] OCR already initialized");
-            return Ok(());
-        }
+    if state.engine.initialized() {
+        println!("[Rust] OCR already initialized");
+        return Ok(());
     }
 
-    let detector = std::thread::Builder::new()
-        .stack_size(8 * 1024 * 1024) // 8MB stack
-        .spawn(|| {
-            println!("[Rust] Loading comic text detector...");
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
-            rt.block_on(async {
-                comic_ocr::comic_text_detector::ComicTextDetector::load(false).await
-            })
-        })
-        .map_err(|e| format!("Failed to spawn thread: {}", e))?
-        .join()
-        .map_err(|_| "Thread panicked".to_string())?
-        .map_err(|e| {
-            let err = format!("Failed to load detector: {}", e);
-            println!("[Rust] Error: {}", err);
-            err
-        })?;
-    println!("[Rust] Detector loaded");
+    // Concurrent calls wait for the first one to load the models; after a
+    // failure the next call tries again.
+    state.engine.get_or_try_init(load_engine).await?;
+    state.jobs.notify_ready();
 
-    let ocr = std::thread::Builder::new()
+    Ok(())
+}
+
+async fn load_engine() -> Result<OcrEngine, String> {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    std::thread::Builder::new()
         .stack_size(8 * 1024 * 1024) // 8MB stack
-        .spawn(|| {
-            println!("[Rust] Loading manga OCR...");
+        .spawn(move || {
+            println!("[Rust] Loading OCR models...");
             let rt = tokio::runtime::Builder::new_current_thread()
                 .enable_all()
                 .build()
                 .unwrap();
-            rt.block_on(async { comic_ocr::manga_ocr::MangaOcr::load(false).await })
+            let _ = sender.send(rt.block_on(OcrEngine::load(false, EngineOptions::default())));
         })
-        .map_err(|e| format!("Failed to spawn thread: {}", e))?
-        .join()
+        .map_err(|e| format!("Failed to spawn thread: {}", e))?;
+
+    let engine = receiver
+        .await
         .map_err(|_| "Thread panicked".to_string())?
         .map_err(|e| {
-            let err = format!("Failed to load OCR: {}", e);
+            let err = format!("Failed to load OCR: {:#}", e);
             println!("[Rust] Error: {}", err);
             err
         })?;
     println!("[Rust] OCR loaded");
-
-    {
-        let mut det_guard = state.detector.lock().unwrap();
-        *det_guard = Some(detector);
-    }
-
-    {
-        let mut ocr_guard = state.ocr.lock().unwrap();
-        *ocr_guard = Some(ocr);
-    }
-
-    Ok(())
+    Ok(engine)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let detector = Arc::new(Mutex::new(None));
-    let ocr = Arc::new(Mutex::new(None));
-
     let state = AppState {
-        archives: Mutex::new(HashMap::new()),
-        ocr: ocr.clone(),
-        detector: detector.clone(),
+        archives: Mutex::new(ArchiveCache::new(archive_cache::DEFAULT_BUDGET_BYTES)),
+        engine: tokio::sync::OnceCell::new(),
+        jobs: OcrJobs::new(),
+        requests: Mutex::new(HashMap::new()),
     };
 
     tauri::Builder::default()
@@ -500,6 +677,7 @@ pub fn run() {
                 println!("no matches. {}", event.id().as_ref())
             }
         })
+        .register_asynchronous_uri_scheme_protocol(protocol::SCHEME, protocol::handle)
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             get_content,
@@ -508,9 +686,21 @@ pub fn run() {
             play_audio,
             open_cbz,
             close_cbz,
+            is_directory,
+            get_metadata,
+            get_chapters,
+            get_lost_entries,
+            set_archive_cache_budget,
             get_page,
             get_page_with_ocr,
-            init_ocr
+            init_ocr,
+            cancel_ocr_request,
+            pause_ocr_job,
+            resume_ocr_job,
+            cancel_ocr_job,
+            get_ocr_job_progress,
+            mokuro::import_mokuro,
+            mokuro::export_mokuro
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use cbz::mokuro::{read_mokuro_pages, write_mokuro_pages};
+use cbz::{MokuroVolume, OcrBlock, OcrPage, OcrSidecar, SourceKind};
+use tauri::{AppHandle, Manager};
+
+use crate::archive_cache::replace_file;
+use crate::{AppState, page_image};
+
+/// Embeds the results of Mokuro for the open archive at `path`, read from a
+/// `.mokuro` file or an `_ocr/<volume>` folder, and returns how many pages
+/// they cover.
+///
+/// The archive is closed while its file is replaced, and reopened with the
+/// results afterwards.
+#[tauri::command]
+pub async fn import_mokuro(
+    app: AppHandle,
+    path: String,
+    mokuro_path: String,
+) -> Result<usize, String> {
+    tokio::task::spawn_blocking(move || embed_mokuro(&app.state::<AppState>(), &path, &mokuro_path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn embed_mokuro(state: &AppState, path: &str, mokuro_path: &str) -> Result<usize, String> {
+    println!("[Rust] import_mokuro called: {} <- {}", path, mokuro_path);
+    if SourceKind::of(Path::new(path)) != SourceKind::Zip {
+        return Err("Only CBZ archives can hold OCR results".to_string());
+    }
+    let pages = {
+        let mut archives = state.archives.lock().unwrap();
+        let archive = archives.get_mut(path).ok_or("Archive not opened")?;
+        archive.pages()
+    };
+
+    let mokuro_path = Path::new(mokuro_path);
+    let sidecar = if mokuro_path.is_dir() {
+        read_mokuro_pages(mokuro_path, &pages)
+    } else {
+        std::fs::read(mokuro_path)
+            .map_err(cbz::CbzError::from)
+            .and_then(|data| MokuroVolume::parse(&data))
+            .map(|volume| volume.to_sidecar(&pages))
+    }
+    .map_err(|e| format!("Failed to read Mokuro results: {}", e))?;
+
+    replace_file(&state.archives, path, || {
+        cbz::embed_ocr_sidecar(path, &sidecar)
+            .map_err(|e| format!("Failed to embed OCR results: {}", e))
+    })?;
+    println!(
+        "[Rust] Imported Mokuro results for {} of {} pages",
+        sidecar.pages.len(),
+        pages.len()
+    );
+    Ok(sidecar.pages.len())
+}
+
+/// Writes the OCR results of the open archive at `path`, embedded or from
+/// the background job, for Mokuro readers: as a `.mokuro` file when
+/// `out_path` has that extension, as per-page files in that folder
+/// otherwise. Returns how many pages have results.
+#[tauri::command]
+pub async fn export_mokuro(
+    app: AppHandle,
+    path: String,
+    out_path: String,
+) -> Result<usize, String> {
+    tokio::task::spawn_blocking(move || write_mokuro(&app.state::<AppState>(), &path, &out_path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn write_mokuro(state: &AppState, path: &str, out_path: &str) -> Result<usize, String> {
+    println!("[Rust] export_mokuro called: {} -> {}", path, out_path);
+    let job_results = state.jobs.results(path);
+
+    let (mut sidecar, pages, info) = {
+        let mut archives = state.archives.lock().unwrap();
+        let sidecar = archives
+            .ocr_sidecar(path)
+            .cloned()
+            .unwrap_or_else(|| OcrSidecar::new(comic_ocr::MODEL_VERSION));
+        let archive = archives.get_mut(path).ok_or("Archive not opened")?;
+        (sidecar, archive.pages(), archive.metadata().ok().flatten())
+    };
+
+    for (name, results) in job_results {
+        // Only the page size is missing from the job's results. The lock is
+        // taken page by page so that the reader is not held up meanwhile.
+        let page = {
+            let mut archives = state.archives.lock().unwrap();
+            let archive = archives.get_mut(path).ok_or("Archive not opened")?;
+            page_image::probe(archive, &name)?
+        };
+        let (width, height) = (page.width, page.height);
+        let blocks = results
+            .into_iter()
+            .map(|result| OcrBlock {
+                text: result.text,
+                bbox: result.bbox.map(|v| v as u32),
+                confidence: result.confidence,
+                ..OcrBlock::default()
+            })
+            .collect();
+        sidecar.pages.insert(
+            name,
+            OcrPage {
+                width,
+                height,
+                blocks,
+            },
+        );
+    }
+
+    let out_path = Path::new(out_path);
+    let is_mokuro_file = out_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mokuro"));
+    if is_mokuro_file {
+        let volume = Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let title = info
+            .and_then(|info| info.series.or(info.title))
+            .unwrap_or_else(|| volume.clone());
+        let mokuro = MokuroVolume::from_sidecar(&sidecar, &pages, &title, &volume);
+        std::fs::write(out_path, mokuro.to_json())
+            .map_err(|e| format!("Failed to write {}: {}", out_path.display(), e))?;
+    } else {
+        write_mokuro_pages(&sidecar, out_path)
+            .map_err(|e| format!("Failed to write Mokuro pages: {}", e))?;
+    }
+    println!("[Rust] Exported {} pages", sidecar.pages.len());
+    Ok(sidecar.pages.len())
+}
@@ -0,0 +1,348 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Condvar, Mutex};
+
+use comic_ocr::pipeline::CancellationToken;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{AppState, OcrResult};
+
+pub const OCR_PROGRESS_EVENT: &str = "ocr-progress";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Idle,
+    Running,
+    Paused,
+    Cancelled,
+    Finished,
+    Failed,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct OcrProgress {
+    path: String,
+    page_name: Option<String>,
+    done: usize,
+    total: usize,
+    status: JobStatus,
+    // Why the job failed.
+    error: Option<String>,
+}
+
+struct JobState {
+    // Bumped every time a job is started or cancelled, so a worker from a
+    // previous archive notices it is stale and exits.
+    generation: u64,
+    path: Option<String>,
+    pages: Vec<String>,
+    pending: HashSet<usize>,
+    focus: usize,
+    results: HashMap<String, Vec<OcrResult>>,
+    status: JobStatus,
+    error: Option<String>,
+    ocr_ready: bool,
+    cancel: CancellationToken,
+}
+
+impl JobState {
+    // Current page first, then the pages after it, then the rest.
+    fn next_page(&self) -> Option<usize> {
+        let after = self.pending.iter().filter(|&&i| i >= self.focus).min();
+        after.or_else(|| self.pending.iter().min()).copied()
+    }
+
+    fn progress(&self, page_name: Option<String>) -> OcrProgress {
+        OcrProgress {
+            path: self.path.clone().unwrap_or_default(),
+            page_name,
+            done: self.pages.len() - self.pending.len(),
+            total: self.pages.len(),
+            status: self.status,
+            error: self.error.clone(),
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<JobState>,
+    wake: Condvar,
+    page_done: tokio::sync::Notify,
+}
+
+/// Background OCR of every page of the open archive.
+///
+/// Only one job runs at a time: opening another archive replaces it.
+#[derive(Clone)]
+pub struct OcrJobs {
+    shared: Arc<Shared>,
+}
+
+impl OcrJobs {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(JobState {
+                    generation: 0,
+                    path: None,
+                    pages: Vec::new(),
+                    pending: HashSet::new(),
+                    focus: 0,
+                    results: HashMap::new(),
+                    status: JobStatus::Idle,
+                    error: None,
+                    ocr_ready: false,
+                    cancel: CancellationToken::new(),
+                }),
+                wake: Condvar::new(),
+                page_done: tokio::sync::Notify::new(),
+            }),
+        }
+    }
+
+    pub fn start(&self, app: AppHandle, path: String, pages: Vec<String>) {
+        let (generation, cancel) = {
+            let mut state = self.shared.state.lock().unwrap();
+            if state.path.as_deref() == Some(path.as_str())
+                && !matches!(state.status, JobStatus::Cancelled | JobStatus::Failed)
+            {
+                return;
+            }
+            state.generation += 1;
+            state.cancel.cancel();
+            state.cancel = CancellationToken::new();
+            state.path = Some(path);
+            state.pending = (0..pages.len()).collect();
+            state.pages = pages;
+            state.focus = 0;
+            state.results.clear();
+            state.status = JobStatus::Running;
+            state.error = None;
+            (state.generation, state.cancel.clone())
+        };
+        self.shared.wake.notify_all();
+        self.shared.page_done.notify_waiters();
+
+        let shared = self.shared.clone();
+        let spawned = std::thread::Builder::new()
+            .name("ocr-job".to_string())
+            .stack_size(4 * 1024 * 1024) // 4MB stack for inference
+            .spawn(move || run_worker(app, shared, generation, cancel));
+        if let Err(e) = spawned {
+            println!("[Rust] Failed to spawn OCR job: {}", e);
+        }
+    }
+
+    /// Moves `page_name` to the front of the queue; pages after it follow.
+    pub fn focus(&self, path: &str, page_name: &str) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.path.as_deref() != Some(path) {
+            return;
+        }
+        if let Some(index) = state.pages.iter().position(|p| p == page_name) {
+            state.focus = index;
+        }
+    }
+
+    pub fn pause(&self) {
+        self.set_status(JobStatus::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.set_status(JobStatus::Running);
+    }
+
+    pub fn cancel(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.status == JobStatus::Idle {
+            return;
+        }
+        state.generation += 1;
+        state.cancel.cancel();
+        state.status = JobStatus::Cancelled;
+        state.pending.clear();
+        drop(state);
+        self.shared.wake.notify_all();
+        self.shared.page_done.notify_waiters();
+    }
+
+    /// Wakes a worker waiting for the OCR models to be loaded.
+    pub fn notify_ready(&self) {
+        self.shared.state.lock().unwrap().ocr_ready = true;
+        self.shared.wake.notify_all();
+    }
+
+    /// Wakes callers of [`OcrJobs::wait_for`] so they re-check their token.
+    pub fn wake_waiters(&self) {
+        self.shared.page_done.notify_waiters();
+    }
+
+    /// Waits for the background job to OCR `page_name`.
+    ///
+    /// Returns `None` when no running job will produce it (another archive,
+    /// paused or cancelled), so the caller can run OCR itself, or when the
+    /// caller's own request was cancelled.
+    pub async fn wait_for(
+        &self,
+        path: &str,
+        page_name: &str,
+        cancel: &CancellationToken,
+    ) -> Option<Vec<OcrResult>> {
+        loop {
+            let notified = self.shared.page_done.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if cancel.is_cancelled() {
+                return None;
+            }
+            {
+                let state = self.shared.state.lock().unwrap();
+                if state.path.as_deref() != Some(path) {
+                    return None;
+                }
+                if let Some(results) = state.results.get(page_name) {
+                    return Some(results.clone());
+                }
+                if state.status != JobStatus::Running {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Results of the pages of `path` OCR'd so far.
+    pub fn results(&self, path: &str) -> HashMap<String, Vec<OcrResult>> {
+        let state = self.shared.state.lock().unwrap();
+        if state.path.as_deref() != Some(path) {
+            return HashMap::new();
+        }
+        state.results.clone()
+    }
+
+    pub fn progress(&self) -> OcrProgress {
+        self.shared.state.lock().unwrap().progress(None)
+    }
+
+    fn set_status(&self, status: JobStatus) {
+        let mut state = self.shared.state.lock().unwrap();
+        if !matches!(state.status, JobStatus::Running | JobStatus::Paused) {
+            return;
+        }
+        state.status = status;
+        drop(state);
+        self.shared.wake.notify_all();
+        self.shared.page_done.notify_waiters();
+    }
+}
+
+fn run_worker(app: AppHandle, shared: Arc<Shared>, generation: u64, cancel: CancellationToken) {
+    let app_state = app.state::<AppState>();
+    loop {
+        let (path, index, page_name) = {
+            let mut state = shared.state.lock().unwrap();
+            loop {
+                if state.generation != generation {
+                    return;
+                }
+                if state.status == JobStatus::Running && state.ocr_ready {
+                    break;
+                }
+                state = shared.wake.wait(state).unwrap();
+            }
+            let Some(index) = state.next_page() else {
+                state.status = JobStatus::Finished;
+                let progress = state.progress(None);
+                drop(state);
+                let _ = app.emit(OCR_PROGRESS_EVENT, progress);
+                shared.page_done.notify_waiters();
+                return;
+            };
+            (
+                state.path.clone().unwrap_or_default(),
+                index,
+                state.pages[index].clone(),
+            )
+        };
+
+        let image_data = {
+            let mut archives = app_state.archives.lock().unwrap();
+            archives
+                .get_mut(&path)
+                .map(|archive| archive.read_page(&page_name))
+        };
+        let results = match image_data {
+            Some(Ok(image)) if !crate::page_image::can_decode(image.format) => {
+                println!(
+                    "[Rust] OCR job: skipping {} ({})",
+                    page_name,
+                    image.mime_type()
+                );
+                None
+            }
+            Some(Ok(image)) => match crate::page_image::decode(&image) {
+                Ok(img) => {
+                    let Some(engine) = app_state.engine.get() else {
+                        fail(&app, &shared, generation, "the OCR models are not loaded");
+                        return;
+                    };
+                    match crate::ocr_image(engine, img, &cancel) {
+                        Some(results) => Some(results),
+                        // Only a newer job or `cancel` fires the token.
+                        None => return,
+                    }
+                }
+                Err(e) => {
+                    println!("[Rust] OCR job: failed to decode {}: {}", page_name, e);
+                    Some(Vec::new())
+                }
+            },
+            Some(Err(e)) => {
+                println!("[Rust] OCR job: failed to read {}: {}", page_name, e);
+                Some(Vec::new())
+            }
+            None => {
+                println!("[Rust] OCR job: archive {} is no longer open", path);
+                let mut state = shared.state.lock().unwrap();
+                if state.generation != generation {
+                    return;
+                }
+                state.status = JobStatus::Cancelled;
+                let progress = state.progress(None);
+                drop(state);
+                let _ = app.emit(OCR_PROGRESS_EVENT, progress);
+                shared.page_done.notify_waiters();
+                return;
+            }
+        };
+
+        let mut state = shared.state.lock().unwrap();
+        if state.generation != generation {
+            return;
+        }
+        state.pending.remove(&index);
+        // Skipped pages get no results, so they are not exported as empty.
+        if let Some(results) = results {
+            state.results.insert(page_name.clone(), results);
+        }
+        let progress = state.progress(Some(page_name));
+        drop(state);
+        let _ = app.emit(OCR_PROGRESS_EVENT, progress);
+        shared.page_done.notify_waiters();
+    }
+}
+
+/// Stops the job of `generation`, if still current, with `error`.
+fn fail(app: &AppHandle, shared: &Shared, generation: u64, error: &str) {
+    println!("[Rust] OCR job failed: {}", error);
+    let mut state = shared.state.lock().unwrap();
+    if state.generation != generation {
+        return;
+    }
+    state.status = JobStatus::Failed;
+    state.error = Some(error.to_string());
+    let progress = state.progress(None);
+    drop(state);
+    let _ = app.emit(OCR_PROGRESS_EVENT, progress);
+    shared.page_done.notify_waiters();
+}
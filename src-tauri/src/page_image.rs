@@ -0,0 +1,125 @@
+use std::io::Cursor;
+
+use cbz::{ImageEntry, ImageFormat};
+use image::{DynamicImage, ImageBuffer, ImageOutputFormat};
+
+use crate::archive_cache::CachedArchive;
+
+/// Bytes read from a page to find its size, enough unless large metadata,
+/// such as an embedded thumbnail, comes before the image header.
+const HEADER_LEN: u64 = 64 * 1024;
+
+/// Format and size of a page, see [`probe`].
+pub struct PageInfo {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Whether the webview can show `format` without help.
+///
+/// TIFF and JPEG XL only render in WebKit, so they are always transcoded to
+/// keep pages looking the same on every platform.
+pub fn is_webview_native(format: ImageFormat) -> bool {
+    !matches!(format, ImageFormat::Tiff | ImageFormat::Jxl)
+}
+
+/// Whether [`decode`] can read `format`, which OCR needs.
+///
+/// AVIF is shown by the webview but not decoded: the image crate only reads
+/// it through the system dav1d library, which yonde does not depend on. Such
+/// pages are left without OCR results rather than counted as failures.
+pub fn can_decode(format: ImageFormat) -> bool {
+    format != ImageFormat::Avif
+}
+
+/// Mime type the page is served with, after any transcoding.
+pub fn served_mime_type(format: ImageFormat) -> &'static str {
+    if is_webview_native(format) {
+        format.mime_type()
+    } else {
+        "image/png"
+    }
+}
+
+/// Returns the bytes to serve for `image`, transcoded to PNG when the webview
+/// cannot display its format.
+pub fn into_displayable(image: ImageEntry) -> Result<Vec<u8>, String> {
+    if is_webview_native(image.format) {
+        return Ok(image.data);
+    }
+    println!(
+        "[Rust] Transcoding {} ({}) to PNG",
+        image.name,
+        image.mime_type()
+    );
+    let decoded = decode(&image)?;
+    let mut png = Vec::new();
+    decoded
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+/// Reads the format and size of the page `name` from its first bytes, and
+/// the whole page only when they do not hold the size.
+pub fn probe(archive: &mut CachedArchive, name: &str) -> Result<PageInfo, String> {
+    let header = archive
+        .read_page_header(name, HEADER_LEN)
+        .map_err(|e| e.to_string())?;
+    let (width, height) = match dimensions(&header.data) {
+        Ok(size) => size,
+        Err(_) => {
+            let image = archive.read_page(name).map_err(|e| e.to_string())?;
+            dimensions(&image.data)?
+        }
+    };
+    Ok(PageInfo {
+        format: header.format,
+        width,
+        height,
+    })
+}
+
+/// Reads the dimensions from the image header without decoding the pixels.
+pub fn dimensions(data: &[u8]) -> Result<(u32, u32), String> {
+    let size = imagesize::blob_size(data).map_err(|e| e.to_string())?;
+    Ok((size.width as u32, size.height as u32))
+}
+
+/// Decodes a page for OCR or transcoding.
+pub fn decode(image: &ImageEntry) -> Result<DynamicImage, String> {
+    match image.format {
+        ImageFormat::Avif => Err(format!(
+            "Cannot decode {}: AVIF is not supported",
+            image.name
+        )),
+        ImageFormat::Jxl => decode_jxl(&image.data),
+        _ => image::load_from_memory(&image.data).map_err(|e| e.to_string()),
+    }
+}
+
+fn decode_jxl(data: &[u8]) -> Result<DynamicImage, String> {
+    let jxl = jxl_oxide::JxlImage::builder()
+        .read(Cursor::new(data))
+        .map_err(|e| e.to_string())?;
+    let render = jxl.render_frame(0).map_err(|e| e.to_string())?;
+
+    let mut stream = render.stream();
+    let (width, height, channels) = (stream.width(), stream.height(), stream.channels());
+    let mut samples = vec![0f32; width as usize * height as usize * channels as usize];
+    stream.write_to_buffer(&mut samples);
+    let pixels: Vec<u8> = samples
+        .into_iter()
+        .map(|sample| (sample.clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+
+    let decoded = match channels {
+        1 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+        2 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8),
+        3 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+        4 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8),
+        _ => None,
+    };
+    decoded.ok_or_else(|| format!("Unsupported JPEG XL layout: {} channels", channels))
+}
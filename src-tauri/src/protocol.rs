@@ -0,0 +1,98 @@
+use percent_encoding::{NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use tauri::http::{Request, Response, StatusCode, header};
+use tauri::{AppHandle, Manager, UriSchemeContext, UriSchemeResponder};
+
+use crate::{AppState, page_image};
+
+pub const SCHEME: &str = "yonde";
+
+// Custom schemes are served from `http://<scheme>.localhost` on Windows and
+// Android, and from `<scheme>://localhost` everywhere else.
+#[cfg(any(windows, target_os = "android"))]
+const BASE_URL: &str = "http://yonde.localhost";
+#[cfg(not(any(windows, target_os = "android")))]
+const BASE_URL: &str = "yonde://localhost";
+
+/// URL the webview loads `page_name` of the archive opened from `path` with.
+///
+/// `version` is that of [`crate::archive_cache::ArchiveCache::version`], so a
+/// file changed on disk gets new URLs rather than cached pages.
+pub fn page_url(path: &str, page_name: &str, version: &str) -> String {
+    format!(
+        "{}/archive/{}/{}?v={}",
+        BASE_URL,
+        utf8_percent_encode(path, NON_ALPHANUMERIC),
+        utf8_percent_encode(page_name, NON_ALPHANUMERIC),
+        version
+    )
+}
+
+/// Serves `/archive/<path>/<page>` with the entry bytes, transcoded when the
+/// webview cannot display them.
+pub fn handle(
+    ctx: UriSchemeContext<'_, tauri::Wry>,
+    request: Request<Vec<u8>>,
+    responder: UriSchemeResponder,
+) {
+    let app = ctx.app_handle().clone();
+    // Reading the entry can take a while on large archives, keep it off the
+    // webview thread, on the runtime's bounded pool rather than a thread per
+    // request.
+    tauri::async_runtime::spawn_blocking(move || responder.respond(respond(&app, &request)));
+}
+
+fn respond(app: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let Some((path, page_name)) = parse_path(request.uri().path()) else {
+        return error(StatusCode::BAD_REQUEST, "Invalid page URL");
+    };
+
+    let state = app.state::<AppState>();
+    let image = {
+        let mut archives = state.archives.lock().unwrap();
+        let Some(archive) = archives.get_mut(&path) else {
+            return error(StatusCode::NOT_FOUND, "Archive not opened");
+        };
+        archive.read_page(&page_name)
+    };
+
+    let image = match image {
+        Ok(image) => image,
+        Err(e) => return error(StatusCode::NOT_FOUND, &e.to_string()),
+    };
+    let mime_type = page_image::served_mime_type(image.format);
+    let data = match page_image::into_displayable(image) {
+        Ok(data) => data,
+        Err(e) => return error(StatusCode::UNSUPPORTED_MEDIA_TYPE, &e),
+    };
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, mime_type)
+        .header(header::CONTENT_LENGTH, data.len())
+        // Page URLs are unique per archive version and entry.
+        .header(header::CACHE_CONTROL, "private, max-age=86400, immutable")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(data)
+        .unwrap()
+}
+
+fn parse_path(path: &str) -> Option<(String, String)> {
+    let mut segments = path.trim_start_matches('/').split('/');
+    if segments.next()? != "archive" {
+        return None;
+    }
+    let archive = percent_decode_str(segments.next()?).decode_utf8().ok()?;
+    let page = percent_decode_str(segments.next()?).decode_utf8().ok()?;
+    if segments.next().is_some() {
+        return None;
+    }
+    Some((archive.into_owned(), page.into_owned()))
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Vec<u8>> {
+    println!("[Rust] {} {}", status, message);
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(message.as_bytes().to_vec())
+        .unwrap()
+}
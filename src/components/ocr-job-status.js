@@ -0,0 +1,124 @@
+// Progress of the background OCR of the open archive, with pause, resume and
+// cancel. Hidden while no job has run.
+class OcrJobStatus extends HTMLElement {
+  constructor() {
+    super();
+    this.attachShadow({ mode: 'open' });
+    this._unlisten = null;
+  }
+
+  connectedCallback() {
+    this.shadowRoot.innerHTML = `
+      <style>
+        :host {
+          display: inline-flex;
+          align-items: center;
+          gap: var(--spacing-xs, 4px);
+          padding: var(--spacing-xs, 4px) var(--spacing-sm, 8px);
+          background: var(--bg-elevated, #252525);
+          border: 1px solid var(--border, #3a3a3a);
+          border-radius: var(--radius-md, 8px);
+          font-size: var(--font-size-sm, 12px);
+          color: var(--fg-muted, #888888);
+        }
+        :host([hidden]) {
+          display: none;
+        }
+        progress {
+          width: 80px;
+          height: 6px;
+          accent-color: var(--accent, #4a9eff);
+        }
+        .count {
+          min-width: 7ch;
+          text-align: right;
+          font-variant-numeric: tabular-nums;
+        }
+        :host([status="failed"]) .label {
+          color: var(--error, #ef4444);
+        }
+        button {
+          width: 24px;
+          height: 24px;
+          padding: 0;
+          border: 1px solid var(--border, #3a3a3a);
+          border-radius: var(--radius-sm, 4px);
+          background: transparent;
+          color: var(--fg, #e8e8e8);
+          cursor: pointer;
+        }
+        button:hover:not(:disabled) {
+          border-color: var(--accent, #4a9eff);
+          color: var(--accent, #4a9eff);
+        }
+        button[hidden] {
+          display: none;
+        }
+      </style>
+      <span class="label" id="label">OCR</span>
+      <progress id="bar" max="1" value="0"></progress>
+      <span class="count" id="count"></span>
+      <button id="pauseBtn" title="Pause OCR" aria-label="Pause OCR">⏸</button>
+      <button id="resumeBtn" title="Resume OCR" aria-label="Resume OCR" hidden>▶</button>
+      <button id="cancelBtn" title="Stop OCR of this archive" aria-label="Stop OCR">✕</button>
+    `;
+
+    this._label = this.shadowRoot.getElementById('label');
+    this._bar = this.shadowRoot.getElementById('bar');
+    this._count = this.shadowRoot.getElementById('count');
+    this._pauseBtn = this.shadowRoot.getElementById('pauseBtn');
+    this._resumeBtn = this.shadowRoot.getElementById('resumeBtn');
+    this._cancelBtn = this.shadowRoot.getElementById('cancelBtn');
+
+    this._pauseBtn.addEventListener('click', () => this._command('pause_ocr_job'));
+    this._resumeBtn.addEventListener('click', () => this._command('resume_ocr_job'));
+    this._cancelBtn.addEventListener('click', () => this._command('cancel_ocr_job'));
+
+    this.hidden = true;
+    if (!window.__TAURI__) return;
+    window.__TAURI__.event
+      .listen('ocr-progress', (e) => this.update(e.payload))
+      .then((unlisten) => { this._unlisten = unlisten; });
+    this._command('get_ocr_job_progress');
+  }
+
+  disconnectedCallback() {
+    if (this._unlisten) this._unlisten();
+    this._unlisten = null;
+  }
+
+  // Shows `progress`, as returned by the OCR job commands.
+  update(progress) {
+    const { done, total, status, error } = progress;
+    this.hidden = status === 'idle';
+    this.setAttribute('status', status);
+
+    const labels = {
+      running: 'OCR',
+      paused: 'OCR paused',
+      cancelled: 'OCR stopped',
+      finished: 'OCR done',
+      failed: 'OCR failed',
+    };
+    this._label.textContent = labels[status] || 'OCR';
+    this._label.title = error || '';
+    this._bar.max = Math.max(total, 1);
+    this._bar.value = done;
+    this._count.textContent = `${done}/${total}`;
+
+    const active = status === 'running' || status === 'paused';
+    this._pauseBtn.hidden = status !== 'running';
+    this._resumeBtn.hidden = status !== 'paused';
+    this._cancelBtn.hidden = !active;
+  }
+
+  async _command(name) {
+    try {
+      this.update(await window.__TAURI__.core.invoke(name));
+    } catch (err) {
+      console.error(`[OcrJobStatus] ${name} failed:`, err);
+    }
+  }
+}
+
+customElements.define('ocr-job-status', OcrJobStatus);
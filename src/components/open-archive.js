@@ -0,0 +1,20 @@
+const { invoke } = window.__TAURI__.core;
+
+// Opens the archive, asking for a password until the right one is given or
+// the prompt is cancelled. Resolves to the archive's page names.
+export async function openArchive(path) {
+  let password = null;
+  for (;;) {
+    try {
+      return await invoke("open_cbz", { path, password });
+    } catch (err) {
+      if (err !== "password-required" && err !== "wrong-password") throw err;
+      password = prompt(
+        err === "wrong-password"
+          ? "Wrong password, try again:"
+          : "This comic is password-protected. Password:",
+      );
+      if (password === null) throw "a password is required";
+    }
+  }
+}
@@ -1,3 +1,5 @@
+import { openArchive } from "./open-archive.js";
+
 const { invoke } = window.__TAURI__.core;
 
 class ReaderContainer extends HTMLElement {
@@ -62,6 +64,7 @@ class ReaderContainer extends HTMLElement {
           <reader-nav id="nav" slot="start"></reader-nav>
           <reader-indicator id="indicator" slot="end"></reader-indicator>
           <button id="ocrBtn" slot="end" title="Enable OCR">OCR</button>
+          <ocr-job-status slot="end"></ocr-job-status>
         </reader-toolbar>
       </div>
       <div class="content">
@@ -98,9 +101,7 @@ class ReaderContainer extends HTMLElement {
     this._showLoading(true);
 
     try {
-      this._pages = await invoke("open_cbz", { path });
-      this._pages.sort();
-      console.log("sorted pages", this._pages);
+      this._pages = await openArchive(path);
       this._currentPath = path;
       this._currentIndex = 0;
 
@@ -151,7 +152,7 @@ class ReaderContainer extends HTMLElement {
         //   pageName,
         // });
 
-        const dataUrl = `data:${result.mime_type};base64,${result.image}`;
+        const dataUrl = result.url;
         this._viewer.setImage(dataUrl, result.width, result.height);
 
         this._viewer.clearOverlays();
@@ -167,7 +168,7 @@ class ReaderContainer extends HTMLElement {
           pageName,
         });
 
-        const dataUrl = `data:${result.mime_type};base64,${result.image}`;
+        const dataUrl = result.url;
         this._viewer.setImage(dataUrl, result.width, result.height);
         this._viewer.clearOverlays();
       }
@@ -1,3 +1,17 @@
+const COMIC_EXTENSIONS = [".cbz", ".zip", ".cbt", ".tar", ".cb7", ".7z", ".epub"];
+
+// Archives by extension, and folders of images whatever their name.
+async function isComicPath(path) {
+  const name = path.split(/[\\/]/).pop().toLowerCase();
+  if (COMIC_EXTENSIONS.some((ext) => name.endsWith(ext))) return true;
+  try {
+    return await window.__TAURI__.core.invoke("is_directory", { path });
+  } catch (err) {
+    console.log("[ReaderDropzone] Could not stat", path, err);
+    return false;
+  }
+}
+
 class ReaderDropzone extends HTMLElement {
   constructor() {
     super();
@@ -47,9 +61,9 @@ class ReaderDropzone extends HTMLElement {
         }
       </style>
       <div class="icon">📁</div>
-      <div class="title">Drop CBZ file here</div>
+      <div class="title">Drop a comic or folder here</div>
       <div class="subtitle">or click to browse</div>
-      <input type="file" id="fileInput" accept=".cbz,.zip">
+      <input type="file" id="fileInput" accept=".cbz,.zip,.cbt,.tar,.cb7,.7z,.epub">
     `;
 
     this._fileInput = this.shadowRoot.getElementById("fileInput");
@@ -88,7 +102,7 @@ class ReaderDropzone extends HTMLElement {
 
     const { getCurrentWindow } = window.__TAURI__.window;
 
-    this._unlisten = await getCurrentWindow().onDragDropEvent((event) => {
+    this._unlisten = await getCurrentWindow().onDragDropEvent(async (event) => {
       const dropzoneContainer = document.getElementById("dropzoneContainer");
       const isDropzoneVisible =
         dropzoneContainer && dropzoneContainer.classList.contains("visible");
@@ -121,7 +135,7 @@ class ReaderDropzone extends HTMLElement {
 
       if (event.payload.type === "enter") {
         const path = paths[0];
-        if (path.endsWith(".cbz") || path.endsWith(".zip")) {
+        if (await isComicPath(path)) {
           this._isOverDropzone = true;
           this.setAttribute("dragging", "");
         }
@@ -132,11 +146,11 @@ class ReaderDropzone extends HTMLElement {
         this.removeAttribute("dragging");
         const path = paths[0];
         console.log("[ReaderDropzone] Drop event, path:", path);
-        if (path.endsWith(".cbz") || path.endsWith(".zip")) {
+        if (await isComicPath(path)) {
           console.log("[ReaderDropzone] Dispatching file-selected for:", path);
           this._handleFilePath(path);
         } else {
-          console.log("[ReaderDropzone] File is not cbz/zip");
+          console.log("[ReaderDropzone] File is not a supported comic");
         }
       } else if (event.payload.type === "over") {
         // Just a position update, no action needed